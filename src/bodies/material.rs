@@ -0,0 +1,207 @@
+use super::{volume, RigidbodyComponent, RigidbodyType};
+use bevy::prelude::*;
+
+/// How the coefficients of the two surfaces in a contact are combined. When the two bodies ask
+/// for different rules, the later one in this list wins, so the default gives way to any rule
+/// picked on purpose.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CoefficientCombine {
+    /// The default, so that a body keeps its own coefficients against static level geometry,
+    /// which has none unless given a material.
+    #[default]
+    Max,
+    Average,
+    Min,
+    Multiply,
+}
+
+impl CoefficientCombine {
+    pub fn combine(self, a: f32, b: f32) -> f32 {
+        match self {
+            Self::Max => a.max(b),
+            Self::Average => (a + b) * 0.5,
+            Self::Min => a.min(b),
+            Self::Multiply => a * b,
+        }
+    }
+}
+
+/// Surface and bulk properties of a body. Either insert it on the body entity directly, or add
+/// it to `Assets<PhysicsMaterial>` and share it between bodies through a
+/// [`PhysicsMaterialHandle`]. The body's friction and restitution follow the material, and
+/// dynamic bodies take their mass from its density and their collider's volume.
+#[derive(Component, Asset, TypePath, Clone, Copy, Debug, PartialEq)]
+pub struct PhysicsMaterial {
+    /// Friction coefficient of a sliding contact.
+    pub friction: f32,
+    /// Friction coefficient holding a contact in place before it starts to slide.
+    pub static_friction: f32,
+    pub restitution: f32,
+    /// Mass per unit of volume.
+    pub density: f32,
+    pub friction_combine: CoefficientCombine,
+    pub restitution_combine: CoefficientCombine,
+}
+
+impl PhysicsMaterial {
+    pub fn new(friction: f32, restitution: f32, density: f32) -> Self {
+        Self {
+            friction,
+            static_friction: friction,
+            restitution,
+            density,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for PhysicsMaterial {
+    fn default() -> Self {
+        Self {
+            friction: 0.5,
+            static_friction: 0.5,
+            restitution: 0.,
+            density: 1.,
+            friction_combine: CoefficientCombine::default(),
+            restitution_combine: CoefficientCombine::default(),
+        }
+    }
+}
+
+/// A shared [`PhysicsMaterial`] asset for the body on this entity. A `PhysicsMaterial`
+/// component on the same entity takes precedence.
+#[derive(Component, Clone, Debug, PartialEq)]
+pub struct PhysicsMaterialHandle(pub Handle<PhysicsMaterial>);
+
+/// Copies the material of every body that has one into the body.
+pub(super) fn apply_materials(
+    mut bodies: Query<(
+        &mut RigidbodyComponent,
+        Option<&PhysicsMaterial>,
+        Option<&PhysicsMaterialHandle>,
+    )>,
+    materials: Res<Assets<PhysicsMaterial>>,
+) {
+    for (mut body, material, handle) in bodies.iter_mut() {
+        let Some(material) =
+            material.or_else(|| handle.and_then(|handle| materials.get(&handle.0)))
+        else {
+            continue;
+        };
+
+        body.friction = material.friction;
+        body.static_friction = material.static_friction;
+        body.restitution = material.restitution;
+        body.friction_combine = material.friction_combine;
+        body.restitution_combine = material.restitution_combine;
+
+        if body.rbt == RigidbodyType::Dynamic {
+            let mass = material.density * volume(&body.collider);
+            // only touch the inertia when the mass really changes
+            if mass > 0. && (mass * body.inverse_mass - 1.).abs() > 1e-4 {
+                body.set_mass(mass);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+    use crate::testing::{body, dynamic, physics_app, run, spawn};
+
+    #[test]
+    fn coefficients_combine_by_each_rule() {
+        for (combine, expected) in [
+            (CoefficientCombine::Max, 0.8),
+            (CoefficientCombine::Average, 0.5),
+            (CoefficientCombine::Min, 0.2),
+            (CoefficientCombine::Multiply, 0.16),
+        ] {
+            let combined = combine.combine(0.2, 0.8);
+            assert!(
+                (combined - expected).abs() < 1e-6,
+                "{combine:?} gave {combined}"
+            );
+            assert_eq!(
+                combined,
+                combine.combine(0.8, 0.2),
+                "{combine:?} is not symmetric"
+            );
+        }
+    }
+
+    #[test]
+    fn later_rule_wins_when_the_bodies_disagree() {
+        let mut app = physics_app();
+        let tilt = Quat::from_rotation_z(0.5);
+        let mut ramp = RigidbodyComponent::new_static(Collider::from_cuboid(
+            Vec3::new(5., 0.5, 3.),
+            Vec3::ZERO,
+            tilt,
+        ));
+        ramp.friction = 0.2;
+        ramp.static_friction = 0.2;
+        spawn(&mut app, ramp);
+        // the ramp keeps the default rule, under which the block's own 0.8 would hold it past
+        // tan(0.5), about 0.55
+        let [by_default, by_min] = [
+            (CoefficientCombine::Max, -1.5),
+            (CoefficientCombine::Min, 1.5),
+        ]
+        .map(|(friction_combine, z)| {
+            let start = tilt * Vec3::Y * 0.75 + Vec3::Z * z;
+            let mut block = dynamic(Collider::from_cuboid(Vec3::splat(0.25), start, tilt));
+            block.friction = 0.8;
+            block.static_friction = 0.8;
+            block.friction_combine = friction_combine;
+            (spawn(&mut app, block), start)
+        });
+        run(&mut app, 30);
+
+        let held = body(&app, by_default.0).collider.center - by_default.1;
+        assert!(held.length() < 0.01, "held block moved by {held}");
+        let slid = body(&app, by_min.0).collider.center - by_min.1;
+        assert!(
+            slid.length() > 0.1,
+            "block asking for the minimum only moved by {slid}"
+        );
+    }
+
+    #[test]
+    fn dynamic_bodies_take_their_mass_from_the_density() {
+        let mut app = physics_app();
+        let cuboid = Collider::from_cuboid(Vec3::new(1., 0.5, 0.25), Vec3::ZERO, Quat::IDENTITY);
+        let direct = spawn(&mut app, dynamic(cuboid.clone()));
+        app.world_mut()
+            .entity_mut(direct)
+            .insert(PhysicsMaterial::new(0.3, 0.1, 2.));
+        let handle = app
+            .world_mut()
+            .resource_mut::<Assets<PhysicsMaterial>>()
+            .add(PhysicsMaterial::new(0.3, 0.1, 3.));
+        let shared = spawn(&mut app, dynamic(cuboid));
+        app.world_mut()
+            .entity_mut(shared)
+            .insert(PhysicsMaterialHandle(handle));
+        run(&mut app, 1);
+
+        // the box holds 2 × 1 × 0.5 = 1 unit of volume
+        for (entity, mass) in [(direct, 2.), (shared, 3.)] {
+            let body = body(&app, entity);
+            assert!(
+                (body.inverse_mass - 1. / mass).abs() < 1e-6,
+                "mass {}",
+                1. / body.inverse_mass
+            );
+            let inertia = body.inverse_inertia_tensor.inverse();
+            let expected = Vec3::new(1. + 0.25, 4. + 0.25, 4. + 1.) * mass / 12.;
+            assert!(
+                inertia.abs_diff_eq(Mat3::from_diagonal(expected), 1e-4),
+                "inertia {inertia}"
+            );
+            assert_eq!((body.friction, body.restitution), (0.3, 0.1));
+        }
+    }
+}
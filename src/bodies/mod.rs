@@ -1,15 +1,48 @@
+mod material;
+
 use bevy::prelude::*;
 
 use crate::collisions::{Collider, ColliderShape};
+use material::apply_materials;
+pub use material::{CoefficientCombine, PhysicsMaterial, PhysicsMaterialHandle};
 
 pub struct RigidBodyPlugin;
 
 impl Plugin for RigidBodyPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, apply_forces);
+        app.init_asset::<PhysicsMaterial>()
+            .configure_sets(
+                Update,
+                (
+                    PhysicsSet::Forces,
+                    PhysicsSet::Collisions,
+                    PhysicsSet::Integrate,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                (
+                    (apply_materials, apply_forces)
+                        .chain()
+                        .in_set(PhysicsSet::Forces),
+                    (integrate_positions, update_sleep)
+                        .chain()
+                        .in_set(PhysicsSet::Integrate),
+                ),
+            );
     }
 }
 
+/// Stages of a physics step. Forces change velocities first, contacts are then solved against
+/// those velocities, and only the result moves the bodies.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PhysicsSet {
+    Forces,
+    Collisions,
+    Integrate,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum RigidbodyType {
     Static,
@@ -44,6 +77,13 @@ impl Velocity {
     };
 }
 
+/// Bodies moving slower than this, in units or radians per second, count as resting.
+const SLEEP_SPEED: f32 = 0.05;
+/// Seconds a body has to rest before it falls asleep.
+const TIME_TO_SLEEP: f32 = 0.5;
+
+/// Sleeping bodies are left out of forces, integration and collision detection against static
+/// and other sleeping bodies, until an awake body touches them.
 #[derive(Clone, Default, Eq, PartialEq)]
 pub enum RigidBodyState {
     Asleep,
@@ -73,12 +113,31 @@ pub struct RigidbodyComponent {
     pub collider: Collider,
     pub velocity: Velocity,
     pub inverse_mass: f32,
+    /// Friction coefficient of a sliding contact.
     pub friction: f32,
+    /// Friction coefficient holding a contact in place before it starts to slide. Set to
+    /// `friction` by the constructors.
+    pub static_friction: f32,
+    /// Resistance to rolling over a surface, as a torque per unit of normal force. Zero lets
+    /// round bodies roll forever.
+    pub rolling_friction: f32,
+    /// Resistance to turning about the contact normal, as a torque per unit of normal force.
+    pub spinning_friction: f32,
+    /// How friction, including rolling and spinning friction, and restitution mix with those
+    /// of the other body in a contact.
+    pub friction_combine: CoefficientCombine,
+    pub restitution_combine: CoefficientCombine,
     pub torque: Vec3,
     pub damping: Damping,
+    /// Inverse inertia tensor about `center_of_mass`, in collider local space.
     pub inverse_inertia_tensor: Mat3,
+    /// Offset of the center of mass from the collider center, in collider local space.
+    pub center_of_mass: Vec3,
     pub restitution: f32,
     pub grounded: bool,
+    /// Seconds the body has been resting for. It falls asleep once this reaches
+    /// `TIME_TO_SLEEP`.
+    pub resting_time: f32,
 }
 
 fn cube_inertia_tensor(mass: f32, size: Vec3) -> Mat3 {
@@ -93,6 +152,167 @@ fn cube_inertia_tensor(mass: f32, size: Vec3) -> Mat3 {
     Mat3::from_diagonal(Vec3::new(ix, iy, iz))
 }
 
+fn sphere_inertia_tensor(mass: f32, radius: f32) -> Mat3 {
+    Mat3::from_diagonal(Vec3::splat(0.4 * mass * radius * radius))
+}
+
+fn ellipsoid_inertia_tensor(mass: f32, radii: Vec3) -> Mat3 {
+    let squared = radii * radii;
+    Mat3::from_diagonal(
+        Vec3::new(
+            squared.y + squared.z,
+            squared.x + squared.z,
+            squared.x + squared.y,
+        ) * (mass / 5.),
+    )
+}
+
+fn capsule_inertia_tensor(mass: f32, half_height: f32, radius: f32) -> Mat3 {
+    let h = half_height * 2.;
+    let r2 = radius * radius;
+
+    // split the mass between the cylinder and the two hemispheres by volume
+    let cylinder_volume = std::f32::consts::PI * r2 * h;
+    let sphere_volume = (4. / 3.) * std::f32::consts::PI * r2 * radius;
+    let cylinder_mass = mass * cylinder_volume / (cylinder_volume + sphere_volume);
+    let sphere_mass = mass - cylinder_mass;
+
+    let iy = cylinder_mass * r2 / 2. + sphere_mass * 0.4 * r2;
+    let ix = cylinder_mass * (r2 / 4. + h * h / 12.)
+        + sphere_mass * (0.4 * r2 + h * h / 4. + 3. * h * radius / 8.);
+
+    Mat3::from_diagonal(Vec3::new(ix, iy, ix))
+}
+
+fn cylinder_inertia_tensor(mass: f32, half_height: f32, radius: f32) -> Mat3 {
+    let h = half_height * 2.;
+    let r2 = radius * radius;
+    let ix = mass * (3. * r2 + h * h) / 12.;
+
+    Mat3::from_diagonal(Vec3::new(ix, mass * r2 / 2., ix))
+}
+
+/// Cone inertia about its center of mass, which lies a quarter of the height above the base.
+fn cone_inertia_tensor(mass: f32, half_height: f32, radius: f32) -> Mat3 {
+    let h = half_height * 2.;
+    let r2 = radius * radius;
+    let ix = mass * (3. * r2 / 20. + 3. * h * h / 80.);
+
+    Mat3::from_diagonal(Vec3::new(ix, mass * 3. * r2 / 10., ix))
+}
+
+/// Inertia of a point mass at `offset` about the origin.
+fn parallel_axis(mass: f32, offset: Vec3) -> Mat3 {
+    (Mat3::from_diagonal(Vec3::splat(offset.length_squared()))
+        - Mat3::from_cols(offset * offset.x, offset * offset.y, offset * offset.z))
+        * mass
+}
+
+fn volume(collider: &Collider) -> f32 {
+    use std::f32::consts::PI;
+
+    let (r, h) = (collider.radius, collider.half_height * 2.);
+    match &collider.collider_shape {
+        ColliderShape::Cuboid => collider.half_extents.element_product() * 8.,
+        ColliderShape::Sphere => 4. / 3. * PI * r * r * r,
+        ColliderShape::Capsule => PI * r * r * h + 4. / 3. * PI * r * r * r,
+        ColliderShape::Ellipsoid => 4. / 3. * PI * collider.half_extents.element_product(),
+        ColliderShape::Cylinder => PI * r * r * h,
+        ColliderShape::Cone => PI * r * r * h / 3.,
+        ColliderShape::ConvexHull(hull) => hull.volume,
+        ColliderShape::Compound(children) => children.iter().map(volume).sum(),
+        ColliderShape::TriMesh(_)
+        | ColliderShape::HeightField(_)
+        | ColliderShape::HalfSpace { .. } => 0.,
+    }
+}
+
+/// Center of mass in collider local space and the inertia tensor about it, for a uniform density.
+/// `None` for colliders only static bodies can use.
+fn mass_properties(collider: &Collider, mass: f32) -> Option<(Vec3, Mat3)> {
+    let properties = match &collider.collider_shape {
+        ColliderShape::Cuboid => (
+            Vec3::ZERO,
+            cube_inertia_tensor(mass, collider.half_extents * 2.),
+        ),
+        ColliderShape::Sphere => (Vec3::ZERO, sphere_inertia_tensor(mass, collider.radius)),
+        ColliderShape::Capsule => (
+            Vec3::ZERO,
+            capsule_inertia_tensor(mass, collider.half_height, collider.radius),
+        ),
+        ColliderShape::Ellipsoid => (
+            Vec3::ZERO,
+            ellipsoid_inertia_tensor(mass, collider.half_extents),
+        ),
+        ColliderShape::Cylinder => (
+            Vec3::ZERO,
+            cylinder_inertia_tensor(mass, collider.half_height, collider.radius),
+        ),
+        ColliderShape::Cone => (
+            Vec3::NEG_Y * collider.half_height / 2.,
+            cone_inertia_tensor(mass, collider.half_height, collider.radius),
+        ),
+        ColliderShape::ConvexHull(hull) => (hull.center_of_mass, hull.inertia * mass),
+        ColliderShape::Compound(children) => {
+            // children share the mass by volume
+            let solids: Vec<&Collider> =
+                children.iter().filter(|child| volume(child) > 0.).collect();
+            if solids.is_empty() {
+                // with nothing to spread the mass over, turn like a unit sphere
+                return Some((Vec3::ZERO, sphere_inertia_tensor(mass, 1.)));
+            }
+            let total_volume: f32 = solids.iter().map(|child| volume(child)).sum();
+            // each child sits at its own offset and rotation
+            let parts: Vec<(f32, Vec3, Mat3)> = solids
+                .into_iter()
+                .map(|child| {
+                    let child_mass = mass * volume(child) / total_volume;
+                    let (center, inertia) = mass_properties(child, child_mass)?;
+                    let rotation = Mat3::from_quat(child.rotation);
+                    Some((
+                        child_mass,
+                        child.center + child.rotation * center,
+                        rotation * inertia * rotation.transpose(),
+                    ))
+                })
+                .collect::<Option<_>>()?;
+
+            let center_of_mass = parts
+                .iter()
+                .map(|&(child_mass, center, _)| center * child_mass)
+                .sum::<Vec3>()
+                / mass;
+            let inertia = parts
+                .iter()
+                .map(|&(child_mass, center, inertia)| {
+                    inertia + parallel_axis(child_mass, center - center_of_mass)
+                })
+                .fold(Mat3::ZERO, |total, inertia| total + inertia);
+
+            (center_of_mass, inertia)
+        }
+        ColliderShape::TriMesh(_)
+        | ColliderShape::HeightField(_)
+        | ColliderShape::HalfSpace { .. } => return None,
+    };
+
+    Some(properties)
+}
+
+/// Error for giving a dynamic body a collider that only static bodies can use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StaticOnlyCollider;
+
+impl std::fmt::Display for StaticOnlyCollider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(
+            "triangle mesh, height field and half-space colliders can only be used by static bodies",
+        )
+    }
+}
+
+impl std::error::Error for StaticOnlyCollider {}
+
 impl RigidbodyComponent {
     #[allow(clippy::too_many_arguments)]
     pub fn new_dynamic(
@@ -104,25 +324,30 @@ impl RigidbodyComponent {
         torque: Vec3,
         damping: Damping,
         restitution: f32,
-    ) -> Self {
-        let mut inertia_tensor = Mat3::ZERO;
-        if collider.collider_shape == ColliderShape::Cuboid {
-            inertia_tensor = cube_inertia_tensor(mass, collider.half_extents * 2.);
-        }
+    ) -> Result<Self, StaticOnlyCollider> {
+        let (center_of_mass, inertia_tensor) =
+            mass_properties(&collider, mass).ok_or(StaticOnlyCollider)?;
 
-        Self {
+        Ok(Self {
             state: RigidBodyState::Awake,
             rbt: RigidbodyType::Dynamic,
             collider,
             inverse_mass: 1. / mass,
             friction,
+            static_friction: friction,
+            rolling_friction: 0.,
+            spinning_friction: 0.,
+            friction_combine: CoefficientCombine::default(),
+            restitution_combine: CoefficientCombine::default(),
             velocity: Velocity::new(velocity, angular_velocity),
             torque,
             damping,
             inverse_inertia_tensor: inertia_tensor.inverse(),
+            center_of_mass,
             restitution,
             grounded: false,
-        }
+            resting_time: 0.,
+        })
     }
 
     pub fn new_static(collider: Collider) -> Self {
@@ -131,13 +356,20 @@ impl RigidbodyComponent {
             rbt: RigidbodyType::Static,
             inverse_mass: 0.,
             friction: 0.,
+            static_friction: 0.,
+            rolling_friction: 0.,
+            spinning_friction: 0.,
+            friction_combine: CoefficientCombine::default(),
+            restitution_combine: CoefficientCombine::default(),
             velocity: Velocity::new(Vec3::ZERO, Vec3::ZERO),
             torque: Vec3::ZERO,
             damping: Damping::default(),
             inverse_inertia_tensor: Mat3::ZERO,
+            center_of_mass: Vec3::ZERO,
             restitution: 0.,
             collider,
             grounded: false,
+            resting_time: 0.,
         }
     }
 
@@ -147,13 +379,43 @@ impl RigidbodyComponent {
             rbt: RigidbodyType::Kinematic,
             inverse_mass: 1. / mass,
             friction: 0.1,
+            static_friction: 0.1,
+            rolling_friction: 0.,
+            spinning_friction: 0.,
+            friction_combine: CoefficientCombine::default(),
+            restitution_combine: CoefficientCombine::default(),
             collider,
             velocity: Velocity::ZERO,
             torque: Vec3::ZERO,
             damping: Damping::default(),
             inverse_inertia_tensor: Mat3::ZERO,
+            center_of_mass: Vec3::ZERO,
             restitution: 0.,
             grounded: false,
+            resting_time: 0.,
+        }
+    }
+
+    /// Replaces the collider, recomputing the center of mass and inertia of dynamic bodies.
+    /// Dynamic bodies keep their collider when given one only static bodies can use.
+    pub fn set_collider(&mut self, collider: Collider) -> Result<(), StaticOnlyCollider> {
+        if self.rbt == RigidbodyType::Dynamic {
+            let (center_of_mass, inertia_tensor) =
+                mass_properties(&collider, 1. / self.inverse_mass).ok_or(StaticOnlyCollider)?;
+            self.center_of_mass = center_of_mass;
+            self.inverse_inertia_tensor = inertia_tensor.inverse();
+        }
+        self.collider = collider;
+        Ok(())
+    }
+
+    /// Changes the mass of a dynamic body, scaling its inertia to match.
+    pub fn set_mass(&mut self, mass: f32) {
+        if self.rbt == RigidbodyType::Dynamic {
+            if let Some((_, inertia_tensor)) = mass_properties(&self.collider, mass) {
+                self.inverse_mass = 1. / mass;
+                self.inverse_inertia_tensor = inertia_tensor.inverse();
+            }
         }
     }
 
@@ -171,11 +433,11 @@ impl RigidbodyComponent {
     }
 }
 
-fn apply_forces(mut query: Query<(&mut RigidbodyComponent, &mut Transform)>, time: Res<Time>) {
+fn apply_forces(mut query: Query<&mut RigidbodyComponent>, time: Res<Time>) {
     let gravity = -9.81;
 
-    for (mut body, mut transform) in query.iter_mut() {
-        if body.rbt == RigidbodyType::Static {
+    for mut body in query.iter_mut() {
+        if body.rbt == RigidbodyType::Static || body.state == RigidBodyState::Asleep {
             continue;
         }
 
@@ -183,8 +445,18 @@ fn apply_forces(mut query: Query<(&mut RigidbodyComponent, &mut Transform)>, tim
         let angular_damping = body.damping.angular;
         body.velocity.linear *= 1.0 - linear_damping;
         body.velocity.angular *= 1.0 - angular_damping;
-        let inverse_mass = body.inverse_mass;
-        body.velocity.linear.y += gravity * (1. / inverse_mass) * time.delta_secs();
+        body.velocity.linear.y += gravity * time.delta_secs();
+    }
+}
+
+fn integrate_positions(
+    mut query: Query<(&mut RigidbodyComponent, &mut Transform)>,
+    time: Res<Time>,
+) {
+    for (mut body, mut transform) in query.iter_mut() {
+        if body.rbt == RigidbodyType::Static || body.state == RigidBodyState::Asleep {
+            continue;
+        }
 
         transform.translation.x += body.velocity.linear.x * time.delta_secs();
         transform.translation.z += body.velocity.linear.z * time.delta_secs();
@@ -195,7 +467,10 @@ fn apply_forces(mut query: Query<(&mut RigidbodyComponent, &mut Transform)>, tim
             let rotation_axis = body.velocity.angular.normalize();
             let delta_rotation =
                 Quat::from_axis_angle(rotation_axis, angular_speed * time.delta_secs());
+            // turn about the center of mass, carrying the collider origin around it
+            let offset = body.collider.rotation * body.center_of_mass;
             body.collider.rotation = (delta_rotation * body.collider.rotation).normalize();
+            transform.translation += offset - body.collider.rotation * body.center_of_mass;
         }
 
         body.collider.center = transform.translation;
@@ -204,3 +479,170 @@ fn apply_forces(mut query: Query<(&mut RigidbodyComponent, &mut Transform)>, tim
         }
     }
 }
+
+/// Puts dynamic bodies to sleep once they have rested for `TIME_TO_SLEEP`.
+fn update_sleep(mut query: Query<&mut RigidbodyComponent>, time: Res<Time>) {
+    for mut body in query.iter_mut() {
+        if body.rbt != RigidbodyType::Dynamic || body.state == RigidBodyState::Asleep {
+            continue;
+        }
+
+        if body.velocity.linear.length() > SLEEP_SPEED
+            || body.velocity.angular.length() > SLEEP_SPEED
+        {
+            body.resting_time = 0.;
+            continue;
+        }
+        body.resting_time += time.delta_secs();
+        if body.resting_time >= TIME_TO_SLEEP {
+            body.state = RigidBodyState::Asleep;
+            body.velocity = Velocity::ZERO;
+            body.resting_time = 0.;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compounds_ignore_children_without_volume() {
+        let cuboid = Collider::from_cuboid(Vec3::new(1., 0.5, 0.5), Vec3::ZERO, Quat::IDENTITY);
+        let mesh = Collider::trimesh(vec![Vec3::ZERO, Vec3::X, Vec3::Z], vec![[0, 1, 2]]);
+        let compound = Collider::compound(vec![
+            (Vec3::Y, Quat::IDENTITY, cuboid.clone()),
+            (Vec3::NEG_Y * 3., Quat::IDENTITY, mesh),
+            (Vec3::X, Quat::IDENTITY, Collider::half_space(Vec3::Y)),
+        ]);
+
+        let (center_of_mass, inertia) = mass_properties(&compound, 2.).unwrap();
+        assert!(center_of_mass.abs_diff_eq(Vec3::Y, 1e-5));
+        assert!(inertia.abs_diff_eq(mass_properties(&cuboid, 2.).unwrap().1, 1e-5));
+    }
+
+    #[test]
+    fn empty_compounds_keep_a_finite_inertia() {
+        for compound in [
+            Collider::compound(vec![]),
+            Collider::compound(vec![(
+                Vec3::ZERO,
+                Quat::IDENTITY,
+                Collider::half_space(Vec3::Y),
+            )]),
+        ] {
+            let body = RigidbodyComponent::new_dynamic(
+                1.,
+                compound,
+                0.5,
+                Vec3::ZERO,
+                Vec3::ZERO,
+                Vec3::ZERO,
+                Damping::default(),
+                0.,
+            )
+            .unwrap();
+            assert!(body.center_of_mass.is_finite());
+            assert!(body.inverse_inertia_tensor.is_finite());
+        }
+    }
+
+    /// Center of mass and inertia about it of a uniform solid, summed over the cells of a grid
+    /// filling `half_extents`.
+    fn sampled_mass_properties(
+        mass: f32,
+        half_extents: Vec3,
+        inside: impl Fn(Vec3) -> bool,
+    ) -> (Vec3, Mat3) {
+        let cells = 40;
+        let points: Vec<Vec3> = (0..cells * cells * cells)
+            .map(|i| {
+                let cell = Vec3::new(
+                    (i % cells) as f32,
+                    (i / cells % cells) as f32,
+                    (i / (cells * cells)) as f32,
+                );
+                ((cell + 0.5) / cells as f32 * 2. - 1.) * half_extents
+            })
+            .filter(|&point| inside(point))
+            .collect();
+        let point_mass = mass / points.len() as f32;
+        let center = points.iter().sum::<Vec3>() / points.len() as f32;
+        let inertia = points.iter().fold(Mat3::ZERO, |total, &point| {
+            total + parallel_axis(point_mass, point - center)
+        });
+        (center, inertia)
+    }
+
+    #[test]
+    fn ellipsoid_inertia_matches_its_shape() {
+        let radii = Vec3::new(1., 0.5, 0.25);
+        let (center_of_mass, inertia) = mass_properties(&Collider::ellipsoid(radii), 3.).unwrap();
+        assert_eq!(center_of_mass, Vec3::ZERO);
+
+        let (_, sampled) =
+            sampled_mass_properties(3., radii, |point| (point / radii).length_squared() <= 1.);
+        assert!(inertia.abs_diff_eq(sampled, 0.02), "{inertia} vs {sampled}");
+
+        // with equal radii it is a sphere
+        let (_, round) = mass_properties(&Collider::ellipsoid(Vec3::splat(0.5)), 3.).unwrap();
+        let sphere = mass_properties(&Collider::sphere(0.5), 3.).unwrap().1;
+        assert!(round.abs_diff_eq(sphere, 1e-6));
+    }
+
+    #[test]
+    fn cylinder_and_cone_inertia_match_their_shape() {
+        let (half_height, radius) = (0.6, 0.4);
+        let half_extents = Vec3::new(radius, half_height, radius);
+        let cylinder = Collider::cylinder(half_height, radius);
+        let cone = Collider::cone(half_height, radius);
+        let (cylinder_center, cylinder_inertia) = mass_properties(&cylinder, 2.).unwrap();
+        let (cone_center, cone_inertia) = mass_properties(&cone, 2.).unwrap();
+
+        let (center, inertia) =
+            sampled_mass_properties(2., half_extents, |point| point.xz().length() <= radius);
+        assert!(cylinder_center.abs_diff_eq(center, 1e-3), "{center}");
+        assert!(cylinder_inertia.abs_diff_eq(inertia, 0.01), "{inertia}");
+
+        // the cone narrows from its base at -half_height to its tip at +half_height
+        let (center, inertia) = sampled_mass_properties(2., half_extents, |point| {
+            point.xz().length() <= radius * (half_height - point.y) / (2. * half_height)
+        });
+        assert!(cone_center.abs_diff_eq(center, 0.01), "{center}");
+        assert!(cone_inertia.abs_diff_eq(inertia, 0.01), "{inertia}");
+    }
+
+    #[test]
+    fn dynamic_bodies_refuse_static_only_colliders() {
+        let floor = Collider::trimesh(vec![Vec3::ZERO, Vec3::X, Vec3::Z], vec![[0, 2, 1]]);
+        let dynamic = |collider| {
+            RigidbodyComponent::new_dynamic(
+                1.,
+                collider,
+                0.5,
+                Vec3::ZERO,
+                Vec3::ZERO,
+                Vec3::ZERO,
+                Damping::default(),
+                0.,
+            )
+        };
+        for collider in [
+            floor.clone(),
+            Collider::height_field(vec![vec![0.; 2]; 2], Vec3::ONE),
+            Collider::half_space(Vec3::Y),
+        ] {
+            assert_eq!(dynamic(collider).err(), Some(StaticOnlyCollider));
+        }
+
+        let cuboid = Collider::from_cuboid(Vec3::splat(0.5), Vec3::ZERO, Quat::IDENTITY);
+        let mut body = dynamic(cuboid).unwrap();
+        assert_eq!(body.set_collider(floor.clone()), Err(StaticOnlyCollider));
+        assert_eq!(body.collider.collider_shape, ColliderShape::Cuboid);
+        body.set_mass(2.);
+        assert_eq!(body.inverse_mass, 0.5);
+
+        let mut ground = RigidbodyComponent::new_static(Collider::sphere(1.));
+        assert_eq!(ground.set_collider(floor), Ok(()));
+    }
+}
@@ -0,0 +1,82 @@
+use bevy::prelude::*;
+
+/// Axis aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        points.into_iter().fold(
+            Self::new(Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |aabb, point| Self::new(aabb.min.min(point), aabb.max.max(point)),
+        )
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn merge(&self, other: &Self) -> Self {
+        Self::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.min.cmple(other.max).all() && other.min.cmple(self.max).all()
+    }
+
+    /// Whether the box reaches infinitely far, as the box of a half-space does.
+    pub fn is_unbounded(&self) -> bool {
+        !(self.max - self.min).is_finite()
+    }
+
+    /// Whether `other` lies entirely inside this box.
+    pub fn contains(&self, other: &Self) -> bool {
+        self.min.cmple(other.min).all() && other.max.cmple(self.max).all()
+    }
+
+    /// Box grown by `margin` on every side.
+    pub fn expanded(&self, margin: f32) -> Self {
+        Self::new(self.min - margin, self.max + margin)
+    }
+
+    /// Surface area, the cost measure for tree building.
+    pub fn area(&self) -> f32 {
+        let size = self.max - self.min;
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    /// Bounds of this box after rotating and then translating it.
+    pub fn transformed(&self, translation: Vec3, rotation: Quat) -> Self {
+        let center = translation + rotation * self.center();
+        let rotation = Mat3::from_quat(rotation);
+        let abs = Mat3::from_cols(
+            rotation.x_axis.abs(),
+            rotation.y_axis.abs(),
+            rotation.z_axis.abs(),
+        );
+        let half_extents = abs * self.half_extents();
+        Self::new(center - half_extents, center + half_extents)
+    }
+
+    /// Distance along the ray `origin + direction * t` at which it enters the box, if that is
+    /// within `max_distance`. Rays starting inside the box hit at zero.
+    pub fn ray_hit(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<f32> {
+        let inverse = direction.recip();
+        let t1 = (self.min - origin) * inverse;
+        let t2 = (self.max - origin) * inverse;
+        let enter = t1.min(t2).max_element().max(0.0);
+        let exit = t1.max(t2).min_element().min(max_distance);
+        (enter <= exit).then_some(enter)
+    }
+}
@@ -0,0 +1,352 @@
+use super::collider_systems::get_collision_info;
+use super::dynamic_tree::DynamicTree;
+use super::{Aabb, Collider, CollisionGroups};
+use crate::bodies::Velocity;
+use crate::rays::{Ray, RayHit};
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+/// Broad phase used by `detect_collisions` and by world queries. Sweep and prune by default;
+/// insert `BroadPhase::DynamicTree(default())` instead for large, sparse worlds.
+#[derive(Resource)]
+pub enum BroadPhase {
+    SweepAndPrune(SweepAndPrune),
+    DynamicTree(DynamicTree),
+}
+
+impl Default for BroadPhase {
+    fn default() -> Self {
+        Self::SweepAndPrune(SweepAndPrune::default())
+    }
+}
+
+impl BroadPhase {
+    /// Replaces the tracked boxes with this frame's ones.
+    pub fn update(&mut self, aabbs: impl IntoIterator<Item = (Entity, Aabb)>) {
+        match self {
+            Self::SweepAndPrune(sap) => sap.update(aabbs),
+            Self::DynamicTree(tree) => tree.update(aabbs),
+        }
+    }
+
+    /// Pairs of entities whose boxes overlap, each reported once.
+    pub fn pairs(&self) -> Vec<(Entity, Entity)> {
+        match self {
+            Self::SweepAndPrune(sap) => sap.pairs(),
+            Self::DynamicTree(tree) => tree.pairs(),
+        }
+    }
+
+    /// Entities whose box may overlap `aabb`.
+    pub fn query(&self, aabb: &Aabb) -> Vec<Entity> {
+        let mut entities = vec![];
+        match self {
+            Self::SweepAndPrune(sap) => sap.query(aabb, |entity| entities.push(entity)),
+            Self::DynamicTree(tree) => tree.query(aabb, |entity| entities.push(entity)),
+        }
+        entities
+    }
+
+    /// Entities whose box may be crossed by the ray within `max_distance`.
+    pub fn query_ray(&self, ray: &Ray, max_distance: f32) -> Vec<Entity> {
+        let (origin, direction) = (ray.get_start(), ray.get_dir().normalize_or_zero());
+        let mut entities = vec![];
+        match self {
+            Self::SweepAndPrune(sap) => sap.query_ray(origin, direction, max_distance, |entity| {
+                entities.push(entity)
+            }),
+            Self::DynamicTree(tree) => tree.query_ray(origin, direction, max_distance, |entity| {
+                entities.push(entity)
+            }),
+        }
+        entities
+    }
+
+    /// Closest collider hit by the ray among those interacting with `groups`. Candidates come from
+    /// the boxes of the last physics step and are tested against the colliders and groups returned
+    /// by `collider`.
+    pub fn cast_ray<'a>(
+        &self,
+        ray: &Ray,
+        max_distance: f32,
+        groups: CollisionGroups,
+        collider: impl Fn(Entity) -> Option<(&'a Collider, CollisionGroups)>,
+    ) -> Option<(Entity, RayHit)> {
+        self.query_ray(ray, max_distance)
+            .into_iter()
+            .filter_map(|entity| {
+                let (collider, other) = collider(entity)?;
+                if !groups.interacts_with(&other) {
+                    return None;
+                }
+                Some((entity, collider.cast_ray(ray, max_distance)?))
+            })
+            .min_by(|a, b| a.1.distance.total_cmp(&b.1.distance))
+    }
+
+    /// Entities interacting with `groups` whose collider, as returned by `collider`, overlaps
+    /// `shape`.
+    pub fn intersecting<'a>(
+        &self,
+        shape: &Collider,
+        groups: CollisionGroups,
+        collider: impl Fn(Entity) -> Option<(&'a Collider, CollisionGroups)>,
+    ) -> Vec<Entity> {
+        self.query(&shape.aabb())
+            .into_iter()
+            .filter(|&entity| {
+                collider(entity).is_some_and(|(other, other_groups)| {
+                    groups.interacts_with(&other_groups)
+                        && !get_collision_info(shape, &Velocity::ZERO, other, &Velocity::ZERO)
+                            .is_empty()
+                })
+            })
+            .collect()
+    }
+}
+
+/// Sweep and prune broad phase. Box endpoints along x stay sorted between frames, so the
+/// insertion sort that refreshes them only moves the few endpoints whose order changed.
+#[derive(Default)]
+pub struct SweepAndPrune {
+    endpoints: Vec<Endpoint>,
+    aabbs: HashMap<Entity, Aabb>,
+    /// Entities with infinite boxes, such as half-spaces. They have no place along the sweep
+    /// axis, so they stay out of the endpoints and pair with every other entity.
+    unbounded: Vec<Entity>,
+}
+
+#[derive(Clone, Copy)]
+struct Endpoint {
+    entity: Entity,
+    value: f32,
+    is_min: bool,
+}
+
+impl Endpoint {
+    /// Sort key that puts starts before ends at the same value, so touching boxes overlap.
+    fn precedes(&self, other: &Self) -> bool {
+        self.value < other.value || (self.value == other.value && self.is_min && !other.is_min)
+    }
+}
+
+impl SweepAndPrune {
+    /// Replaces the tracked boxes with this frame's ones. Entities missing from `aabbs` are
+    /// dropped and new ones are added.
+    pub fn update(&mut self, aabbs: impl IntoIterator<Item = (Entity, Aabb)>) {
+        self.aabbs = aabbs.into_iter().collect();
+        self.unbounded = self
+            .aabbs
+            .iter()
+            .filter(|(_, aabb)| aabb.is_unbounded())
+            .map(|(&entity, _)| entity)
+            .collect();
+
+        self.endpoints.retain(|endpoint| {
+            self.aabbs
+                .get(&endpoint.entity)
+                .is_some_and(|aabb| !aabb.is_unbounded())
+        });
+        let mut tracked = vec![];
+        for endpoint in &mut self.endpoints {
+            let aabb = self.aabbs[&endpoint.entity];
+            endpoint.value = if endpoint.is_min {
+                aabb.min.x
+            } else {
+                aabb.max.x
+            };
+            if endpoint.is_min {
+                tracked.push(endpoint.entity);
+            }
+        }
+        tracked.sort_unstable();
+        for (&entity, aabb) in &self.aabbs {
+            if !aabb.is_unbounded() && tracked.binary_search(&entity).is_err() {
+                self.endpoints.extend([
+                    Endpoint {
+                        entity,
+                        value: aabb.min.x,
+                        is_min: true,
+                    },
+                    Endpoint {
+                        entity,
+                        value: aabb.max.x,
+                        is_min: false,
+                    },
+                ]);
+            }
+        }
+
+        for i in 1..self.endpoints.len() {
+            let mut j = i;
+            while j > 0 && self.endpoints[j].precedes(&self.endpoints[j - 1]) {
+                self.endpoints.swap(j, j - 1);
+                j -= 1;
+            }
+        }
+    }
+
+    /// Overlapping pairs, found in one sweep over the sorted endpoints.
+    pub fn pairs(&self) -> Vec<(Entity, Entity)> {
+        let mut active: Vec<Entity> = vec![];
+        let mut pairs = vec![];
+        for endpoint in &self.endpoints {
+            if !endpoint.is_min {
+                active.retain(|&entity| entity != endpoint.entity);
+                continue;
+            }
+
+            let aabb = &self.aabbs[&endpoint.entity];
+            for &other in &active {
+                if self.aabbs[&other].intersects(aabb) {
+                    pairs.push((other, endpoint.entity));
+                }
+            }
+            active.push(endpoint.entity);
+        }
+        for (i, &entity) in self.unbounded.iter().enumerate() {
+            pairs.extend(
+                self.aabbs
+                    .keys()
+                    .filter(|&&other| other != entity && !self.unbounded[..i].contains(&other))
+                    .map(|&other| (entity, other)),
+            );
+        }
+        pairs
+    }
+
+    /// Calls `visit` with every entity whose box overlaps `aabb`, including unbounded ones.
+    pub fn query(&self, aabb: &Aabb, mut visit: impl FnMut(Entity)) {
+        self.unbounded.iter().copied().for_each(&mut visit);
+        for (&entity, other) in &self.aabbs {
+            if !other.is_unbounded() && other.intersects(aabb) {
+                visit(entity);
+            }
+        }
+    }
+
+    /// Calls `visit` with every entity whose box is crossed by the ray within `max_distance`,
+    /// including unbounded ones.
+    pub fn query_ray(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+        mut visit: impl FnMut(Entity),
+    ) {
+        self.unbounded.iter().copied().for_each(&mut visit);
+        for (&entity, aabb) in &self.aabbs {
+            if !aabb.is_unbounded() && aabb.ray_hit(origin, direction, max_distance).is_some() {
+                visit(entity);
+            }
+        }
+    }
+
+    /// Box of `entity` as of the last update.
+    pub fn aabb(&self, entity: Entity) -> Option<Aabb> {
+        self.aabbs.get(&entity).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collisions::ColliderVertexInfo;
+    use crate::testing::{brute_force_pairs, drifting_boxes, normalized};
+
+    #[test]
+    fn sweep_and_prune_pairs_match_brute_force() {
+        let mut sap = SweepAndPrune::default();
+        for frame in 0..40 {
+            let mut boxes = drifting_boxes(frame, 60);
+            // a half-space, which stays out of the endpoints, on every other frame
+            if frame % 4 < 2 {
+                boxes.push((Entity::from_raw(100), Collider::half_space(Vec3::Y).aabb()));
+            }
+            sap.update(boxes.iter().copied());
+
+            let pairs = normalized(sap.pairs());
+            assert!(!pairs.is_empty());
+            assert_eq!(pairs, brute_force_pairs(&boxes), "frame {frame}");
+            assert!(sap
+                .endpoints
+                .iter()
+                .all(|endpoint| endpoint.value.abs() < 1e3));
+        }
+    }
+
+    #[test]
+    fn queries_find_every_overlapping_box() {
+        let boxes = drifting_boxes(0, 60);
+        let query = Aabb::new(Vec3::new(2., -1., -1.), Vec3::new(6., 3., 1.));
+        let ray = Ray::new(Vec3::new(-5., 1., 0.), Vec3::X);
+
+        for mut broad_phase in [
+            BroadPhase::default(),
+            BroadPhase::DynamicTree(DynamicTree::default()),
+        ] {
+            broad_phase.update(boxes.iter().copied());
+            let found = broad_phase.query(&query);
+            let crossed = broad_phase.query_ray(&ray, 30.);
+
+            for (entity, aabb) in &boxes {
+                if aabb.intersects(&query) {
+                    assert!(found.contains(entity));
+                }
+                if aabb.ray_hit(ray.get_start(), Vec3::X, 30.).is_some() {
+                    assert!(crossed.contains(entity));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn world_queries_only_see_interacting_groups() {
+        // three spheres along x: in group 1, in group 2 but only seeing group 1, and in all groups
+        let colliders: Vec<(Entity, Collider, CollisionGroups)> = [
+            CollisionGroups::new(1, CollisionGroups::ALL),
+            CollisionGroups::new(2, 1),
+            CollisionGroups::default(),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(index, groups)| {
+            let mut sphere = Collider::sphere(0.5);
+            sphere.center = Vec3::X * (index as f32 * 2.);
+            (Entity::from_raw(index as u32), sphere, groups)
+        })
+        .collect();
+        let lookup = |entity: Entity| {
+            colliders
+                .iter()
+                .find(|(other, ..)| *other == entity)
+                .map(|(_, collider, groups)| (collider, *groups))
+        };
+        let mut broad_phase = BroadPhase::default();
+        broad_phase.update(
+            colliders
+                .iter()
+                .map(|(entity, collider, _)| (*entity, collider.aabb())),
+        );
+
+        let ray = Ray::new(Vec3::new(-2., 0., 0.), Vec3::X);
+        let first = |groups| {
+            broad_phase
+                .cast_ray(&ray, 10., groups, lookup)
+                .map(|(entity, _)| entity.index())
+        };
+        assert_eq!(first(CollisionGroups::default()), Some(0));
+        assert_eq!(first(CollisionGroups::new(1, 2)), Some(1));
+        // the second sphere does not see group 4 in turn, so the ray passes through it
+        assert_eq!(first(CollisionGroups::new(4, 2)), Some(2));
+        assert_eq!(first(CollisionGroups::new(4, CollisionGroups::NONE)), None);
+
+        let mut shape =
+            Collider::from_cuboid(Vec3::new(3., 0.5, 0.5), Vec3::X * 2., Quat::IDENTITY);
+        shape.vertex_info =
+            ColliderVertexInfo::from_cuboid(&shape.center, &shape.half_extents, &shape.rotation);
+        let mut found = broad_phase.intersecting(&shape, CollisionGroups::new(4, 1 | 2), lookup);
+        found.sort_unstable();
+        assert_eq!(found, vec![Entity::from_raw(0), Entity::from_raw(2)]);
+    }
+}
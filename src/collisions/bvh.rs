@@ -0,0 +1,207 @@
+use super::Aabb;
+use bevy::prelude::*;
+
+const LEAF_SIZE: usize = 4;
+
+/// Bounding volume hierarchy over a fixed set of boxes, built once by median splits.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Bvh {
+    nodes: Vec<BvhNode>,
+    /// Item indices, ordered so that every leaf owns a contiguous range.
+    items: Vec<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum BvhNode {
+    Leaf {
+        aabb: Aabb,
+        start: usize,
+        end: usize,
+    },
+    Branch {
+        aabb: Aabb,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl BvhNode {
+    fn aabb(&self) -> &Aabb {
+        match self {
+            BvhNode::Leaf { aabb, .. } | BvhNode::Branch { aabb, .. } => aabb,
+        }
+    }
+}
+
+impl Bvh {
+    pub(crate) fn new(aabbs: &[Aabb]) -> Self {
+        let mut bvh = Self {
+            nodes: vec![],
+            items: (0..aabbs.len()).collect(),
+        };
+        if !aabbs.is_empty() {
+            bvh.build(aabbs, 0, aabbs.len());
+        }
+        bvh
+    }
+
+    fn build(&mut self, aabbs: &[Aabb], start: usize, end: usize) -> usize {
+        let aabb = self.items[start..end]
+            .iter()
+            .map(|&item| aabbs[item])
+            .reduce(|a, b| a.merge(&b))
+            .unwrap();
+
+        if end - start <= LEAF_SIZE {
+            self.nodes.push(BvhNode::Leaf { aabb, start, end });
+            return self.nodes.len() - 1;
+        }
+
+        // split at the median centroid along the longest axis
+        let extents = aabb.max - aabb.min;
+        let axis = if extents.x >= extents.y && extents.x >= extents.z {
+            0
+        } else if extents.y >= extents.z {
+            1
+        } else {
+            2
+        };
+        let middle = (start + end) / 2;
+        self.items[start..end].select_nth_unstable_by(middle - start, |&a, &b| {
+            aabbs[a].center()[axis].total_cmp(&aabbs[b].center()[axis])
+        });
+
+        let index = self.nodes.len();
+        self.nodes.push(BvhNode::Leaf {
+            aabb,
+            start: 0,
+            end: 0,
+        });
+        let left = self.build(aabbs, start, middle);
+        let right = self.build(aabbs, middle, end);
+        self.nodes[index] = BvhNode::Branch { aabb, left, right };
+        index
+    }
+
+    pub(crate) fn bounds(&self) -> Aabb {
+        self.nodes
+            .first()
+            .map(|node| *node.aabb())
+            .unwrap_or(Aabb::new(Vec3::ZERO, Vec3::ZERO))
+    }
+
+    /// Calls `visit` with every item whose leaf box overlaps `aabb`, which includes every item
+    /// whose own box does.
+    pub(crate) fn query(&self, aabb: &Aabb, mut visit: impl FnMut(usize)) {
+        let mut stack = vec![];
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.aabb().intersects(aabb) {
+                continue;
+            }
+            match *node {
+                BvhNode::Leaf { start, end, .. } => {
+                    self.items[start..end].iter().copied().for_each(&mut visit)
+                }
+                BvhNode::Branch { left, right, .. } => stack.extend([left, right]),
+            }
+        }
+    }
+
+    /// Calls `visit` with every item whose leaf box is crossed by the ray within `max_distance`.
+    pub(crate) fn query_ray(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+        mut visit: impl FnMut(usize),
+    ) {
+        let mut stack = vec![];
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node
+                .aabb()
+                .ray_hit(origin, direction, max_distance)
+                .is_none()
+            {
+                continue;
+            }
+            match *node {
+                BvhNode::Leaf { start, end, .. } => {
+                    self.items[start..end].iter().copied().for_each(&mut visit)
+                }
+                BvhNode::Branch { left, right, .. } => stack.extend([left, right]),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{scattered_boxes, Lcg};
+
+    fn sorted(mut items: Vec<usize>) -> Vec<usize> {
+        items.sort_unstable();
+        items
+    }
+
+    #[test]
+    fn queries_visit_every_overlapping_box_once() {
+        let aabbs = scattered_boxes(&mut Lcg(7), 300, 10.);
+        let bvh = Bvh::new(&aabbs);
+
+        for query in scattered_boxes(&mut Lcg(8), 50, 10.) {
+            let mut found = vec![];
+            bvh.query(&query, |item| found.push(item));
+            let found = sorted(found);
+
+            assert!(found.windows(2).all(|pair| pair[0] != pair[1]));
+            for (item, aabb) in aabbs.iter().enumerate() {
+                if aabb.intersects(&query) {
+                    assert!(found.binary_search(&item).is_ok(), "missed box {item}");
+                }
+            }
+            // leaves hold a handful of items, so the query stays far from a full scan
+            assert!(found.len() < aabbs.len() / 4);
+        }
+    }
+
+    #[test]
+    fn ray_queries_visit_every_box_the_ray_crosses() {
+        let aabbs = scattered_boxes(&mut Lcg(7), 300, 10.);
+        let bvh = Bvh::new(&aabbs);
+
+        for target in scattered_boxes(&mut Lcg(8), 50, 10.) {
+            let origin = Vec3::new(-15., 2., 12.);
+            let direction = (target.center() - origin).normalize();
+            let mut found = vec![];
+            bvh.query_ray(origin, direction, 25., |item| found.push(item));
+            let found = sorted(found);
+
+            assert!(found.windows(2).all(|pair| pair[0] != pair[1]));
+            for (item, aabb) in aabbs.iter().enumerate() {
+                if aabb.ray_hit(origin, direction, 25.).is_some() {
+                    assert!(found.binary_search(&item).is_ok(), "missed box {item}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bounds_cover_every_box() {
+        let aabbs = scattered_boxes(&mut Lcg(9), 100, 10.);
+        let bounds = Bvh::new(&aabbs).bounds();
+
+        assert!(aabbs.iter().all(|aabb| bounds.contains(aabb)));
+        assert_eq!(Bvh::new(&[]).bounds(), Aabb::new(Vec3::ZERO, Vec3::ZERO));
+    }
+}
@@ -1,10 +1,119 @@
+use super::events::{pair_key, SensorOverlaps, SolidContacts};
+use super::filter::can_respond;
+use super::gjk;
+use super::manifold::{manifold, merge, persist};
+use super::trimesh::TriangleSource;
 use crate::prelude::*;
 use bevy::prelude::*;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Rebuilds the collider of every body with `Collider` components on itself or its descendants
+/// as a compound of them, placed relative to the body through their `GlobalTransform`s. Scale is
+/// ignored, and descendants that are bodies of their own are left to themselves. Dynamic bodies
+/// leave out static-only colliders and warn about them once.
+pub(crate) fn aggregate_child_colliders(
+    mut bodies: Query<(Entity, &mut RigidbodyComponent, &GlobalTransform)>,
+    colliders: Query<(&Collider, &GlobalTransform)>,
+    children: Query<&Children>,
+    nested_bodies: Query<(), With<RigidbodyComponent>>,
+    mut warned: Local<HashSet<Entity>>,
+) {
+    for (entity, mut body, body_transform) in bodies.iter_mut() {
+        let inverse_rotation = body_transform.rotation().inverse();
+        let mut parts = vec![];
+        let mut stack = vec![entity];
+        while let Some(current) = stack.pop() {
+            if let Ok((collider, transform)) = colliders.get(current) {
+                if body.rbt == RigidbodyType::Dynamic && collider.collider_shape.is_static_only() {
+                    if warned.insert(entity) {
+                        warn!(
+                            "dynamic body {entity:?} ignores its triangle mesh, height field and \
+                             half-space colliders, which only static bodies can use"
+                        );
+                    }
+                } else {
+                    parts.push((
+                        inverse_rotation * (transform.translation() - body_transform.translation()),
+                        inverse_rotation * transform.rotation(),
+                        collider.clone(),
+                    ));
+                }
+            }
+            if let Ok(descendants) = children.get(current) {
+                stack.extend(
+                    descendants
+                        .iter()
+                        .filter(|&&child| !nested_bodies.contains(child)),
+                );
+            }
+        }
+        if parts.is_empty() {
+            continue;
+        }
+
+        let mut compound = Collider::compound(parts);
+        // skip the mass update while the layout only drifts by transform propagation noise
+        if same_layout(&body.collider, &compound) {
+            continue;
+        }
+        compound.center = body.collider.center;
+        compound.rotation = body.collider.rotation;
+        body.set_collider(compound)
+            .expect("dynamic bodies leave out static-only colliders");
+    }
+}
+
+fn same_layout(a: &Collider, b: &Collider) -> bool {
+    let (ColliderShape::Compound(a), ColliderShape::Compound(b)) =
+        (&a.collider_shape, &b.collider_shape)
+    else {
+        return false;
+    };
+    a.len() == b.len()
+        && a.iter().zip(b.iter()).all(|(a, b)| {
+            same_shape(&a.collider_shape, &b.collider_shape)
+                && a.half_extents == b.half_extents
+                && a.radius == b.radius
+                && a.half_height == b.half_height
+                && a.is_sensor == b.is_sensor
+                && a.center.abs_diff_eq(b.center, 1e-4)
+                && a.rotation.abs_diff_eq(b.rotation, 1e-4)
+        })
+}
+
+/// Shared meshes, height fields, hulls and children are compared by pointer rather than by
+/// their contents.
+fn same_shape(a: &ColliderShape, b: &ColliderShape) -> bool {
+    use ColliderShape::*;
+
+    match (a, b) {
+        (ConvexHull(a), ConvexHull(b)) => Arc::ptr_eq(a, b),
+        (TriMesh(a), TriMesh(b)) => Arc::ptr_eq(a, b),
+        (HeightField(a), HeightField(b)) => Arc::ptr_eq(a, b),
+        (Compound(a), Compound(b)) => Arc::ptr_eq(a, b),
+        _ => a == b,
+    }
+}
 
 pub(crate) fn update_vertices(
-    mut query: Query<(&mut RigidbodyComponent, &mut Transform), With<RigidbodyComponent>>,
+    mut query: Query<(
+        &mut RigidbodyComponent,
+        Option<&GlobalTransform>,
+        Has<Parent>,
+    )>,
 ) {
-    for (mut body, mut transform) in query.iter_mut() {
+    for (mut body, global_transform, has_parent) in query.iter_mut() {
+        // nested static bodies, such as scene nodes, take their world pose from the propagated
+        // transform
+        if let Some(global_transform) = global_transform.filter(|_| has_parent) {
+            if body.rbt == RigidbodyType::Static {
+                body.collider.center = global_transform.translation();
+                body.collider.rotation = global_transform.rotation();
+            }
+        }
+
         //let rot: [f32; 3] = transform.rotation.to_euler(EulerRot::XYZ).into();
         body.collider.axes = [
             body.collider.rotation * Vec3::X,
@@ -12,109 +121,108 @@ pub(crate) fn update_vertices(
             body.collider.rotation * Vec3::Z,
         ];
 
-        body.collider.vertex_info = ColliderVertexInfo::from_cuboid(
-            &body.collider.center,
-            &body.collider.half_extents,
-            &body.collider.rotation,
-        );
-    }
-}
-
-fn rotation_to_align(from: Vec3, to: Vec3) -> Quat {
-    let from = from.normalize();
-    let to = to.normalize();
-    let axis = from.cross(to);
-    let angle = from.angle_between(to);
-    if axis.length_squared() < 1e-6 {
-        if from.dot(to) < 0.0 {
-            // Rotate 180° around any perpendicular axis
-            Quat::from_axis_angle(Vec3::X, std::f32::consts::PI)
-        } else {
-            Quat::IDENTITY
-        }
-    } else {
-        Quat::from_axis_angle(axis.normalize(), angle)
-    }
-}
-
-pub(crate) fn detect_collisions(mut query: Query<(&mut RigidbodyComponent, &mut Transform)>) {
-    let mut items: Vec<_> = query.iter_mut().collect();
-
-    for i in 0..items.len() {
-        for j in (i + 1)..items.len() {
-            let (left, right) = items.split_at_mut(j);
-            let (ref mut body_a, ref mut transform_a) = left[i];
-            let (ref mut body_b, ref mut transform_b) = right[0];
-
-            if let Some(collision_data) = get_collision_info(
-                &body_a.collider,
-                &body_a.velocity,
-                &body_b.collider,
-                &body_b.velocity,
-            ) {
-                // println!(
-                //     "collision -- normal: {:?}, penetration: {}",
-                //     collision_data.normal, collision_data.penetration_depth
-                // );
-                resolve_collision(body_a, transform_a, body_b, transform_b, &collision_data);
-            }
+        if body.collider.collider_shape == ColliderShape::Cuboid {
+            body.collider.vertex_info = ColliderVertexInfo::from_cuboid(
+                &body.collider.center,
+                &body.collider.half_extents,
+                &body.collider.rotation,
+            );
         }
     }
 }
 
-fn resolve_collision(
-    a: &mut RigidbodyComponent,
-    tf_a: &mut Transform,
-    b: &mut RigidbodyComponent,
-    tf_b: &mut Transform,
-    contact: &ContactInfo,
+pub(crate) fn detect_collisions(
+    mut query: Query<(Entity, &mut RigidbodyComponent, Option<&CollisionGroups>)>,
+    mut broad_phase: ResMut<BroadPhase>,
+    pair_filter: Res<PairFilter>,
+    mut sensor_overlaps: ResMut<SensorOverlaps>,
+    mut solid_contacts: ResMut<SolidContacts>,
+    mut contacts: ResMut<Contacts>,
 ) {
-    let total_inverse_mass = a.inverse_mass + b.inverse_mass;
-    if total_inverse_mass == 0.0 {
-        return;
-    }
-
-    let correction = contact.normal * (contact.penetration_depth / total_inverse_mass);
+    let mut previous_contacts = contacts.take();
+    broad_phase.update(
+        query
+            .iter()
+            .map(|(entity, body, ..)| (entity, body.collider.aabb())),
+    );
 
-    let temp_vel = a.velocity.linear;
+    for (a, b) in broad_phase.pairs() {
+        let Ok([(_, mut body_a, groups_a), (_, mut body_b, groups_b)]) = query.get_many_mut([a, b])
+        else {
+            continue;
+        };
+        let (groups_a, groups_b) = (
+            groups_a.copied().unwrap_or_default(),
+            groups_b.copied().unwrap_or_default(),
+        );
+        if !groups_a.interacts_with(&groups_b) {
+            continue;
+        }
+        if !can_respond(&body_a, &body_b) {
+            // resting pairs that fell asleep are not tested, but they still touch and overlap
+            if let Some(contact) = previous_contacts.remove(&pair_key(a, b)) {
+                solid_contacts.0.touch(pair_key(a, b));
+                contacts.insert(contact);
+            }
+            if sensor_overlaps.0.touched_before(&pair_key(a, b)) {
+                sensor_overlaps.0.touch(pair_key(a, b));
+            }
+            continue;
+        }
+        if !pair_filter.allows(a, &body_a, b, &body_b) {
+            continue;
+        }
 
-    let ground_threshold = 0.7;
+        // sensor children of a compound only report overlaps, while the rest of it collides
+        let (sensors, mut infos): (Vec<_>, Vec<_>) = get_collision_info(
+            &body_a.collider,
+            &body_a.velocity,
+            &body_b.collider,
+            &body_b.velocity,
+        )
+        .into_iter()
+        .partition(|info| {
+            is_sensor_part(&body_a.collider, info.child_a)
+                || is_sensor_part(&body_b.collider, info.child_b)
+        });
+        if !sensors.is_empty() {
+            sensor_overlaps.0.touch(pair_key(a, b));
+        }
 
-    if a.rbt != RigidbodyType::Static {
-        a.grounded = contact.normal.dot(Vec3::Y) > ground_threshold;
-        a.collider.center += correction * a.inverse_mass;
-        if a.rbt == RigidbodyType::Static {
-            a.velocity.linear = Vec3::ZERO;
+        let previous = previous_contacts.get(&pair_key(a, b)).map(|contact| {
+            if contact.entity_a == a {
+                contact.info.clone()
+            } else {
+                contact.info.flipped()
+            }
+        });
+        for info in &mut infos {
+            persist(info, previous.as_ref(), &body_a.collider, &body_b.collider);
         }
-        a.velocity.linear *= 1. - a.friction;
-        a.velocity.angular = Vec3::ZERO;
-        a.velocity.linear += a.restitution * (contact.normal + temp_vel) * (1. - a.damping.linear)
-            + contact.b_vel.linear;
-        tf_a.translation = a.collider.center;
-    }
 
-    if a.rbt == RigidbodyType::Dynamic {
-        let world_down = Vec3::Y;
-        let local_down = a.collider.rotation * world_down;
-        let align_rotation: Vec3 = rotation_to_align(local_down, contact.normal)
-            .to_euler(EulerRot::XYZ)
-            .into();
-        println!("!!!");
-        a.velocity.angular += align_rotation * a.inverse_mass * a.velocity.linear.length().max(6.5);
+        // every touching pair of child shapes adds its points to the one contact of the bodies
+        if let Some(collision_data) = merge(infos) {
+            // an awake body touching a sleeping one wakes it up
+            body_a.state = RigidBodyState::Awake;
+            body_b.state = RigidBodyState::Awake;
+            solid_contacts.0.touch(pair_key(a, b));
+            contacts.insert(Contact {
+                entity_a: a,
+                entity_b: b,
+                info: collision_data,
+                impulse: 0.0,
+            });
+        }
     }
+}
 
-    if b.rbt != RigidbodyType::Static {
-        b.grounded = contact.normal.dot(Vec3::Y) > ground_threshold;
-        b.collider.center += correction * b.inverse_mass;
-        if b.rbt == RigidbodyType::Static {
-            b.velocity.linear = Vec3::ZERO;
+/// Whether the collider, or the child of it named by `child` when it is a compound, is a sensor.
+fn is_sensor_part(collider: &Collider, child: Option<usize>) -> bool {
+    collider.is_sensor
+        || match (&collider.collider_shape, child) {
+            (ColliderShape::Compound(children), Some(index)) => children[index].is_sensor,
+            _ => false,
         }
-        b.velocity.angular = Vec3::ZERO;
-        b.velocity.linear *= 1. - b.friction;
-        b.velocity.linear += b.restitution * (contact.normal + temp_vel) * (1. - b.damping.linear)
-            + contact.a_vel.linear;
-        tf_b.translation = b.collider.center;
-    }
 }
 
 fn project_collider(collider: &Collider, axis: Vec3) -> (f32, f32) {
@@ -134,12 +242,96 @@ fn get_overlap(min_a: f32, max_a: f32, min_b: f32, max_b: f32) -> f32 {
     (max_a.min(max_b) - min_a.max(min_b)).max(0.0)
 }
 
-fn get_collision_info(
+/// Contacts between two colliders, one for every touching pair of child shapes when either is
+/// a compound.
+pub(super) fn get_collision_info(
     a: &Collider,
     a_vel: &Velocity,
     b: &Collider,
     b_vel: &Velocity,
-) -> Option<ContactInfo> {
+) -> Vec<ContactInfo> {
+    let parts_a = parts(a);
+    let parts_b = parts(b);
+    let mut contacts = vec![];
+
+    for (child_a, part_a) in &parts_a {
+        for (child_b, part_b) in &parts_b {
+            let compound = child_a.is_some() || child_b.is_some();
+            if compound && !part_a.aabb().intersects(&part_b.aabb()) {
+                continue;
+            }
+
+            if let Some((normal, penetration_depth, contact_point_a, contact_point_b)) =
+                shape_contact(part_a, part_b)
+            {
+                let deepest =
+                    ManifoldPoint::new(contact_point_a, contact_point_b, penetration_depth, None);
+                contacts.push(ContactInfo {
+                    normal,
+                    penetration_depth,
+                    contact_point_a,
+                    contact_point_b,
+                    a_vel: *a_vel,
+                    b_vel: *b_vel,
+                    child_a: *child_a,
+                    child_b: *child_b,
+                    manifold: manifold(part_a, part_b, normal, deepest),
+                });
+            }
+        }
+    }
+
+    contacts
+}
+
+/// The collider itself, or each of its children placed in world space when it is a compound.
+fn parts(collider: &Collider) -> Vec<(Option<usize>, Cow<'_, Collider>)> {
+    match collider.collider_shape {
+        ColliderShape::Compound(_) => collider
+            .world_children()
+            .into_iter()
+            .enumerate()
+            .map(|(index, child)| (Some(index), Cow::Owned(child)))
+            .collect(),
+        _ => vec![(None, Cow::Borrowed(collider))],
+    }
+}
+
+fn shape_contact(a: &Collider, b: &Collider) -> Option<ShapeContact> {
+    use ColliderShape::*;
+
+    let contact = match (&a.collider_shape, &b.collider_shape) {
+        (Cuboid, Cuboid) => cuboid_cuboid(a, b)?,
+        (Sphere, Sphere) => sphere_sphere(a.center, a.radius, b.center, b.radius)?,
+        (Sphere, Cuboid) => sphere_cuboid(a.center, a.radius, b)?,
+        (Capsule, Capsule) => capsule_capsule(a, b)?,
+        (Capsule, Sphere) => capsule_sphere(a, b.center, b.radius)?,
+        (Capsule, Cuboid) => capsule_cuboid(a, b)?,
+        (Cuboid, Sphere) => flip(sphere_cuboid(b.center, b.radius, a)?),
+        (Sphere, Capsule) => flip(capsule_sphere(b, a.center, a.radius)?),
+        (Cuboid, Capsule) => flip(capsule_cuboid(b, a)?),
+        // level geometry never collides with itself
+        (
+            TriMesh(_) | HeightField(_) | HalfSpace { .. },
+            TriMesh(_) | HeightField(_) | HalfSpace { .. },
+        ) => return None,
+        (HalfSpace { normal }, _) => half_space_contact(a, *normal, b)?,
+        (_, HalfSpace { normal }) => flip(half_space_contact(b, *normal, a)?),
+        (_, TriMesh(trimesh)) => convex_triangles(a, b, trimesh.as_ref())?,
+        (TriMesh(trimesh), _) => flip(convex_triangles(b, a, trimesh.as_ref())?),
+        (_, HeightField(field)) => convex_triangles(a, b, field.as_ref())?,
+        (HeightField(field), _) => flip(convex_triangles(b, a, field.as_ref())?),
+        // curved shapes and hulls without a closed form test go through GJK and EPA
+        _ => support_contact(a, b)?,
+    };
+
+    Some(contact)
+}
+
+/// (normal from a to b, penetration depth, contact point on a, contact point on b)
+type ShapeContact = (Vec3, f32, Vec3, Vec3);
+
+fn cuboid_cuboid(a: &Collider, b: &Collider) -> Option<ShapeContact> {
     let axes = a.get_axes(b);
     let mut min_overlap = f32::INFINITY;
     let mut collision_axis = Vec3::ZERO;
@@ -160,12 +352,706 @@ fn get_collision_info(
         }
     }
 
-    Some(ContactInfo {
-        normal: collision_axis,
-        penetration_depth: min_overlap,
-        contact_point_a: a.center,
-        contact_point_b: b.center,
-        a_vel: *a_vel,
-        b_vel: *b_vel,
-    })
+    if collision_axis.dot(b.center - a.center) < 0.0 {
+        collision_axis = -collision_axis;
+    }
+
+    // the smaller of the two touching features lies within the other one, so it locates the contact
+    let (feature_a, count_a) = support_feature(a, collision_axis);
+    let (feature_b, count_b) = support_feature(b, -collision_axis);
+    let use_b = count_b < count_a
+        || (count_b == count_a
+            && b.half_extents.element_product() <= a.half_extents.element_product());
+
+    let (point_a, point_b) = if use_b {
+        (feature_b + collision_axis * min_overlap, feature_b)
+    } else {
+        (feature_a, feature_a - collision_axis * min_overlap)
+    };
+
+    Some((collision_axis, min_overlap, point_a, point_b))
+}
+
+/// Centroid of the cuboid vertices furthest along `direction` (a corner, edge or face)
+/// and the number of vertices it was built from.
+fn support_feature(cuboid: &Collider, direction: Vec3) -> (Vec3, usize) {
+    let vertices = &cuboid.vertex_info.vertices;
+    let max = vertices
+        .iter()
+        .map(|vertex| vertex.dot(direction))
+        .fold(f32::MIN, f32::max);
+    let feature: Vec<Vec3> = vertices
+        .iter()
+        .copied()
+        .filter(|vertex| vertex.dot(direction) > max - 1e-3)
+        .collect();
+
+    (
+        feature.iter().sum::<Vec3>() / feature.len().max(1) as f32,
+        feature.len(),
+    )
+}
+
+fn support_contact(a: &Collider, b: &Collider) -> Option<ShapeContact> {
+    let contact = gjk::penetration(a, b)?;
+    Some((
+        contact.normal,
+        contact.depth,
+        contact.point_a,
+        contact.point_b,
+    ))
+}
+
+/// Contact of a convex collider with a half-space, found from its support point against the plane.
+fn half_space_contact(plane: &Collider, normal: Vec3, other: &Collider) -> Option<ShapeContact> {
+    let normal = plane.rotation * normal;
+    let deepest = other.support(-normal);
+    let depth = normal.dot(plane.center - deepest);
+
+    (depth > 0.0).then_some((normal, depth, deepest + normal * depth, deepest))
+}
+
+/// Deepest contact between a convex collider and the triangles of a mesh or height field collider.
+fn convex_triangles(
+    convex: &Collider,
+    mesh: &Collider,
+    triangles: &impl TriangleSource,
+) -> Option<ShapeContact> {
+    let bounds = convex.aabb_in_frame(mesh.center, mesh.rotation);
+
+    triangles
+        .triangles_in(&bounds)
+        .into_iter()
+        .filter_map(|index| {
+            let local = triangles.triangle(index);
+            let triangle = Triangle::new(
+                mesh.center + mesh.rotation * local.a,
+                mesh.center + mesh.rotation * local.b,
+                mesh.center + mesh.rotation * local.c,
+            );
+            convex_triangle(convex, &triangle, triangles.convex_edges(index))
+        })
+        .max_by(|x, y| x.1.total_cmp(&y.1))
+}
+
+/// Contact against a single mesh triangle, which is solid on the back of its counter-clockwise
+/// winding. Unless it lies on a convex edge of the mesh, the contact is pushed along the face
+/// normal, so shapes slide across the seams between triangles instead of catching on them.
+fn convex_triangle(
+    convex: &Collider,
+    triangle: &Triangle,
+    convex_edges: [bool; 3],
+) -> Option<ShapeContact> {
+    let contact = gjk::penetration(convex, triangle)?;
+    let face_normal = triangle.normal();
+
+    // edge i runs from vertex i to vertex i + 1, so it lies opposite vertex i + 2
+    let weights = gjk::barycentric(contact.point_b, triangle.a, triangle.b, triangle.c);
+    let on_edge = [weights.z, weights.x, weights.y].map(|weight| weight < 1e-3);
+    if (0..3).any(|edge| on_edge[edge] && convex_edges[edge])
+        && contact.normal.dot(face_normal) <= 0.0
+    {
+        return Some((
+            contact.normal,
+            contact.depth,
+            contact.point_a,
+            contact.point_b,
+        ));
+    }
+
+    let deepest = convex.support(-face_normal);
+    let depth = face_normal.dot(triangle.a - deepest);
+    (depth > 0.0).then_some((-face_normal, depth, deepest, deepest + face_normal * depth))
+}
+
+fn flip((normal, depth, point_a, point_b): ShapeContact) -> ShapeContact {
+    (-normal, depth, point_b, point_a)
+}
+
+fn sphere_sphere(
+    center_a: Vec3,
+    radius_a: f32,
+    center_b: Vec3,
+    radius_b: f32,
+) -> Option<ShapeContact> {
+    let delta = center_b - center_a;
+    let radii = radius_a + radius_b;
+    let distance_squared = delta.length_squared();
+    if distance_squared >= radii * radii {
+        return None;
+    }
+
+    let distance = distance_squared.sqrt();
+    // concentric spheres have no preferred direction, push them apart along y
+    let normal = if distance > 1e-6 {
+        delta / distance
+    } else {
+        Vec3::Y
+    };
+
+    Some((
+        normal,
+        radii - distance,
+        center_a + normal * radius_a,
+        center_b - normal * radius_b,
+    ))
+}
+
+fn sphere_cuboid(center: Vec3, radius: f32, cuboid: &Collider) -> Option<ShapeContact> {
+    let (local_normal, distance, local_point) = cuboid_signed_distance(center, cuboid);
+    if distance >= radius {
+        return None;
+    }
+
+    // normal points from the sphere towards the cuboid
+    let normal = -(cuboid.rotation * local_normal);
+    Some((
+        normal,
+        radius - distance,
+        center + normal * radius,
+        cuboid.center + cuboid.rotation * local_point,
+    ))
+}
+
+/// Signed distance from `point` to the surface of a cuboid, negative inside.
+/// Returns the outward normal and the closest surface point, both in the cuboid's local space.
+fn cuboid_signed_distance(point: Vec3, cuboid: &Collider) -> (Vec3, f32, Vec3) {
+    let local = cuboid.rotation.inverse() * (point - cuboid.center);
+    let half = cuboid.half_extents;
+    let closest = local.clamp(-half, half);
+
+    let delta = local - closest;
+    let distance_squared = delta.length_squared();
+    if distance_squared > 1e-12 {
+        // outside the box, the closest point lies on its surface
+        let distance = distance_squared.sqrt();
+        return (delta / distance, distance, closest);
+    }
+
+    // inside the box, the nearest face decides
+    let distances = half - local.abs();
+    let axis = if distances.x <= distances.y && distances.x <= distances.z {
+        0
+    } else if distances.y <= distances.z {
+        1
+    } else {
+        2
+    };
+    let mut normal = Vec3::ZERO;
+    normal[axis] = if local[axis] < 0.0 { -1.0 } else { 1.0 };
+    let mut closest = local;
+    closest[axis] = half[axis] * normal[axis];
+    (normal, -distances[axis], closest)
+}
+
+fn closest_point_on_segment(point: Vec3, start: Vec3, end: Vec3) -> Vec3 {
+    let segment = end - start;
+    let length_squared = segment.length_squared();
+    if length_squared < 1e-12 {
+        return start;
+    }
+    let t = ((point - start).dot(segment) / length_squared).clamp(0.0, 1.0);
+    start + segment * t
+}
+
+/// Closest points between segments `p1 q1` and `p2 q2` (Ericson, Real-Time Collision Detection 5.1.9).
+pub(super) fn closest_points_segments(p1: Vec3, q1: Vec3, p2: Vec3, q2: Vec3) -> (Vec3, Vec3) {
+    let d1 = q1 - p1;
+    let d2 = q2 - p2;
+    let r = p1 - p2;
+    let a = d1.length_squared();
+    let e = d2.length_squared();
+    let f = d2.dot(r);
+
+    if a < 1e-12 && e < 1e-12 {
+        return (p1, p2);
+    }
+
+    let (s, t) = if a < 1e-12 {
+        (0.0, (f / e).clamp(0.0, 1.0))
+    } else {
+        let c = d1.dot(r);
+        if e < 1e-12 {
+            ((-c / a).clamp(0.0, 1.0), 0.0)
+        } else {
+            let b = d1.dot(d2);
+            let denom = a * e - b * b;
+            let mut s = if denom > 1e-12 {
+                ((b * f - c * e) / denom).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let mut t = (b * s + f) / e;
+            if t < 0.0 {
+                t = 0.0;
+                s = (-c / a).clamp(0.0, 1.0);
+            } else if t > 1.0 {
+                t = 1.0;
+                s = ((b - c) / a).clamp(0.0, 1.0);
+            }
+            (s, t)
+        }
+    };
+
+    (p1 + d1 * s, p2 + d2 * t)
+}
+
+fn capsule_capsule(a: &Collider, b: &Collider) -> Option<ShapeContact> {
+    let (start_a, end_a) = a.segment();
+    let (start_b, end_b) = b.segment();
+    let (point_a, point_b) = closest_points_segments(start_a, end_a, start_b, end_b);
+    sphere_sphere(point_a, a.radius, point_b, b.radius)
+}
+
+fn capsule_sphere(capsule: &Collider, center: Vec3, radius: f32) -> Option<ShapeContact> {
+    let (start, end) = capsule.segment();
+    let point = closest_point_on_segment(center, start, end);
+    sphere_sphere(point, capsule.radius, center, radius)
+}
+
+fn capsule_cuboid(capsule: &Collider, cuboid: &Collider) -> Option<ShapeContact> {
+    // the signed distance to a convex shape is convex along the segment,
+    // so a golden section search finds the deepest point of the inner segment
+    let (start, end) = capsule.segment();
+    let distance_at = |t: f32| cuboid_signed_distance(start.lerp(end, t), cuboid).1;
+
+    let ratio = 0.618_034;
+    let (mut low, mut high) = (0.0f32, 1.0f32);
+    let mut t1 = high - ratio * (high - low);
+    let mut t2 = low + ratio * (high - low);
+    let (mut d1, mut d2) = (distance_at(t1), distance_at(t2));
+    for _ in 0..24 {
+        if d1 < d2 {
+            high = t2;
+            t2 = t1;
+            d2 = d1;
+            t1 = high - ratio * (high - low);
+            d1 = distance_at(t1);
+        } else {
+            low = t1;
+            t1 = t2;
+            d1 = d2;
+            t2 = low + ratio * (high - low);
+            d2 = distance_at(t2);
+        }
+    }
+
+    // the end points are not covered by the open search interval
+    let t = [0.0, (low + high) * 0.5, 1.0]
+        .into_iter()
+        .min_by(|&x, &y| distance_at(x).total_cmp(&distance_at(y)))
+        .unwrap_or(0.5);
+
+    // once the segment pierces the box its deepest point no longer tells which way to push it
+    // out, so the contact comes from EPA on the whole capsule instead
+    if distance_at(t) <= 0.0 {
+        if let Some(contact) = support_contact(capsule, cuboid) {
+            return Some(contact);
+        }
+    }
+
+    sphere_cuboid(start.lerp(end, t), capsule.radius, cuboid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        body, body_mut, dynamic, ground, physics_app, record, recorded, run, spawn,
+    };
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    #[test]
+    fn static_bodies_keep_their_collider_pose_unless_nested() {
+        let mut app = physics_app();
+        let floor =
+            Collider::from_cuboid(Vec3::new(5., 0.5, 5.), Vec3::NEG_Y * 0.5, Quat::IDENTITY);
+        let root = app
+            .world_mut()
+            .spawn((
+                RigidbodyComponent::new_static(floor.clone()),
+                Transform::default(),
+            ))
+            .id();
+        let nested = app
+            .world_mut()
+            .spawn(Transform::from_xyz(3., 0., 0.))
+            .with_children(|parent| {
+                parent.spawn((
+                    RigidbodyComponent::new_static(floor),
+                    Transform::from_xyz(0., 1., 0.),
+                ));
+            })
+            .id();
+        // transforms propagate at the end of a frame, so the nested pose shows up a frame later
+        run(&mut app, 2);
+
+        assert_eq!(body(&app, root).collider.center, Vec3::NEG_Y * 0.5);
+        let child = app.world().get::<Children>(nested).unwrap()[0];
+        assert_eq!(body(&app, child).collider.center, Vec3::new(3., 1., 0.));
+    }
+
+    #[test]
+    fn static_and_sleeping_pairs_are_skipped_until_woken() {
+        let mut app = physics_app();
+        let cuboid = |center| Collider::from_cuboid(Vec3::splat(0.5), center, Quat::IDENTITY);
+        let ground = spawn(&mut app, ground());
+        let wall = spawn(
+            &mut app,
+            RigidbodyComponent::new_static(cuboid(Vec3::X * 3.)),
+        );
+        let resting = spawn(&mut app, dynamic(cuboid(Vec3::Y * 0.5)));
+        run(&mut app, 90);
+
+        let contacts = app.world().resource::<Contacts>();
+        assert!(!contacts.are_touching(ground, wall));
+        assert!(body(&app, resting).state == RigidBodyState::Asleep);
+
+        // asleep, the box neither falls nor is tested, but it still touches the ground
+        let center = body(&app, resting).collider.center;
+        run(&mut app, 30);
+        assert_eq!(body(&app, resting).collider.center, center);
+        assert_eq!(body(&app, resting).velocity.linear, Vec3::ZERO);
+        let contacts = app.world().resource::<Contacts>();
+        assert!(contacts.are_touching(ground, resting));
+
+        // an awake body landing on it wakes it up
+        let mut ball = dynamic(Collider::sphere(0.3));
+        ball.collider.center = center + Vec3::Y * 1.;
+        let ball = spawn(&mut app, ball);
+        body_mut(&mut app, ball).velocity.linear = Vec3::NEG_Y * 2.;
+        run(&mut app, 15);
+        let contacts = app.world().resource::<Contacts>();
+        assert!(contacts.are_touching(ball, resting));
+        assert!(body(&app, resting).state == RigidBodyState::Awake);
+    }
+
+    #[test]
+    fn bodies_only_collide_when_both_groups_take_in_each_other() {
+        let mut app = physics_app();
+        let ground = spawn(&mut app, ground());
+        app.world_mut()
+            .entity_mut(ground)
+            .insert(CollisionGroups::new(1, !4));
+
+        let boxes: Vec<Entity> = [
+            CollisionGroups::new(2, 1),
+            // filters out the ground
+            CollisionGroups::new(2, 2),
+            // filtered out by the ground
+            CollisionGroups::new(4, CollisionGroups::ALL),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(index, groups)| {
+            let center = Vec3::new(index as f32 * 2. - 2., 0.5, 0.);
+            let cuboid = Collider::from_cuboid(Vec3::splat(0.5), center, Quat::IDENTITY);
+            let entity = spawn(&mut app, dynamic(cuboid));
+            app.world_mut().entity_mut(entity).insert(groups);
+            entity
+        })
+        .collect();
+        run(&mut app, 30);
+
+        let contacts = app.world().resource::<Contacts>();
+        assert!(contacts.are_touching(ground, boxes[0]));
+        assert!(body(&app, boxes[0]).collider.center.y > 0.4);
+        for &entity in &boxes[1..] {
+            assert!(!contacts.are_touching(ground, entity));
+            assert!(body(&app, entity).collider.center.y < 0.);
+        }
+    }
+
+    #[test]
+    fn sensor_overlaps_last_while_both_bodies_sleep() {
+        let mut app = physics_app();
+        record::<OverlapStarted>(&mut app);
+        record::<OverlapEnded>(&mut app);
+        let ground = spawn(&mut app, ground());
+        // a box whose sensor child reaches into the ground it rests on
+        let mut sensor = Collider::sphere(0.8);
+        sensor.is_sensor = true;
+        let cuboid = Collider::from_cuboid(Vec3::splat(0.5), Vec3::ZERO, Quat::IDENTITY);
+        let mut compound = Collider::compound(vec![
+            (Vec3::ZERO, Quat::IDENTITY, cuboid),
+            (Vec3::ZERO, Quat::IDENTITY, sensor),
+        ]);
+        compound.center = Vec3::Y * 0.5;
+        let resting = spawn(&mut app, dynamic(compound));
+        run(&mut app, 90);
+
+        assert!(body(&app, resting).state == RigidBodyState::Asleep);
+        assert_eq!(
+            recorded::<OverlapStarted>(&app),
+            [OverlapStarted(ground.min(resting), ground.max(resting))]
+        );
+        assert!(recorded::<OverlapEnded>(&app).is_empty());
+    }
+
+    fn flat_grounds() -> Vec<Collider> {
+        let corners = vec![
+            Vec3::new(-2., 0., -2.),
+            Vec3::new(2., 0., -2.),
+            Vec3::new(2., 0., 2.),
+            Vec3::new(-2., 0., 2.),
+        ];
+        vec![
+            Collider::height_field(vec![vec![0.; 3]; 3], Vec3::new(4., 1., 4.)),
+            Collider::trimesh(corners, vec![[0, 2, 1], [0, 3, 2]]),
+        ]
+    }
+
+    #[test]
+    fn resting_shapes_are_pushed_out_of_flat_ground_along_its_surface() {
+        for shape in [Collider::cylinder(0.5, 0.5), Collider::cone(0.5, 0.5)] {
+            for ground in flat_grounds() {
+                // over vertices, edges and the diagonal seams of the triangles alike
+                for step in 0..49 {
+                    let offset = Vec3::new((step % 7) as f32, 0., (step / 7) as f32) * 0.25
+                        - Vec3::new(0.75, 0., 0.75);
+                    let mut shape = shape.clone();
+                    shape.center = offset + Vec3::Y * 0.4;
+
+                    let (normal, depth, _, _) = shape_contact(&ground, &shape)
+                        .expect("the shape sinks 0.1 into the ground");
+                    assert!(normal.abs_diff_eq(Vec3::Y, 1e-4), "normal {normal:?}");
+                    assert!((depth - 0.1).abs() < 1e-4, "depth {depth}");
+
+                    let (normal, depth, _, _) = shape_contact(&shape, &ground).unwrap();
+                    assert!(normal.abs_diff_eq(Vec3::NEG_Y, 1e-4), "normal {normal:?}");
+                    assert!((depth - 0.1).abs() < 1e-4, "depth {depth}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn meshes_push_shapes_out_along_their_winding() {
+        let corners = vec![
+            Vec3::new(-2., 0., -2.),
+            Vec3::new(2., 0., -2.),
+            Vec3::new(2., 0., 2.),
+            Vec3::new(-2., 0., 2.),
+        ];
+        let up = Collider::trimesh(corners.clone(), vec![[0, 2, 1], [0, 3, 2]]);
+        let down = Collider::trimesh(corners, vec![[0, 1, 2], [0, 2, 3]]);
+
+        // even with its center below the surface, a sphere is pushed out above it
+        let mut sphere = Collider::sphere(0.5);
+        sphere.center = Vec3::NEG_Y * 0.1;
+        let (normal, depth, _, _) = shape_contact(&up, &sphere).unwrap();
+        assert!(normal.abs_diff_eq(Vec3::Y, 1e-4), "normal {normal:?}");
+        assert!((depth - 0.6).abs() < 1e-4, "depth {depth}");
+
+        sphere.center = Vec3::Y * 0.1;
+        let (normal, depth, _, _) = shape_contact(&down, &sphere).unwrap();
+        assert!(normal.abs_diff_eq(Vec3::NEG_Y, 1e-4), "normal {normal:?}");
+        assert!((depth - 0.6).abs() < 1e-4, "depth {depth}");
+    }
+
+    #[test]
+    fn height_fields_push_shapes_up_out_of_the_ground() {
+        let field = Collider::height_field(vec![vec![0.; 3]; 3], Vec3::new(4., 1., 4.));
+        let cuboid = Collider::from_cuboid(Vec3::splat(0.5), Vec3::ZERO, Quat::IDENTITY);
+        for (mut shape, height) in [
+            (Collider::sphere(0.5), -0.1),
+            (cuboid.clone(), -0.05),
+            (cuboid, 0.3),
+        ] {
+            shape.center = Vec3::new(0.3, height, -0.2);
+            if let ColliderShape::Cuboid = shape.collider_shape {
+                shape.vertex_info = ColliderVertexInfo::from_cuboid(
+                    &shape.center,
+                    &shape.half_extents,
+                    &shape.rotation,
+                );
+            }
+
+            let (normal, depth, _, _) = shape_contact(&field, &shape).unwrap();
+            assert!(normal.abs_diff_eq(Vec3::Y, 1e-4), "normal {normal:?}");
+            assert!((depth - (0.5 - height)).abs() < 1e-4, "depth {depth}");
+        }
+
+        // on a slope the push follows the surface through the sphere's center
+        let slope = Collider::height_field(vec![vec![0., 1.]; 2], Vec3::new(4., 1., 4.));
+        let mut sphere = Collider::sphere(0.5);
+        sphere.center = Vec3::new(0., 0.5, 0.);
+        let (normal, depth, _, _) = shape_contact(&slope, &sphere).unwrap();
+        let expected = Vec3::new(-1., 4., 0.).normalize();
+        assert!(normal.abs_diff_eq(expected, 1e-4), "normal {normal:?}");
+        assert!((depth - 0.5).abs() < 1e-4, "depth {depth}");
+    }
+
+    #[test]
+    fn cylinders_and_cones_touch_with_their_rims_sides_and_tips() {
+        let floor =
+            Collider::from_cuboid(Vec3::new(5., 0.5, 5.), Vec3::NEG_Y * 0.5, Quat::IDENTITY);
+        let lying = Quat::from_rotation_z(FRAC_PI_2);
+        let upside_down = Quat::from_rotation_x(PI);
+        // (shape, rotation, height of its lowest point above its center)
+        for (mut shape, rotation, reach) in [
+            (Collider::cylinder(0.6, 0.4), Quat::IDENTITY, 0.6),
+            (Collider::cylinder(0.6, 0.4), lying, 0.4),
+            (Collider::cone(0.6, 0.4), Quat::IDENTITY, 0.6),
+            (Collider::cone(0.6, 0.4), upside_down, 0.6),
+            (Collider::cone(0.6, 0.4), lying, 0.4),
+        ] {
+            shape.rotation = rotation;
+            shape.center = Vec3::Y * (reach - 0.1);
+
+            let (normal, depth, _, _) =
+                shape_contact(&shape, &floor).expect("the shape sinks 0.1 into the floor");
+            assert!(normal.abs_diff_eq(Vec3::NEG_Y, 1e-3), "normal {normal:?}");
+            assert!((depth - 0.1).abs() < 1e-3, "depth {depth}");
+        }
+
+        // the bottom rim of an upright cylinder cuts into the slanted side of a cone beside it
+        let cylinder = Collider::cylinder(0.6, 0.4);
+        let mut cone = Collider::cone(0.6, 0.4);
+        cone.center = Vec3::X * 0.75;
+        let (normal, depth, _, _) = shape_contact(&cylinder, &cone).unwrap();
+        let side = Vec3::new(1.2, -0.4, 0.).normalize();
+        assert!(normal.abs_diff_eq(side, 2e-2), "normal {normal:?}");
+        assert!((depth - 0.05 * side.x).abs() < 2e-3, "depth {depth}");
+
+        cone.center = Vec3::X * 0.85;
+        assert!(shape_contact(&cylinder, &cone).is_none());
+    }
+
+    #[test]
+    fn half_spaces_push_shapes_out_along_their_normal_from_any_depth() {
+        let mut plane = Collider::half_space(Vec3::Y);
+        plane.center = Vec3::new(1., 2., 3.);
+        plane.rotation = Quat::from_rotation_x(0.4);
+        let normal = plane.rotation * Vec3::Y;
+
+        let cuboid = Collider::from_cuboid(Vec3::splat(0.5), Vec3::ZERO, Quat::IDENTITY);
+        for shape in [
+            cuboid,
+            Collider::sphere(0.5),
+            Collider::capsule(0.5, 0.3),
+            Collider::cone(0.6, 0.4),
+        ] {
+            let lowest = (shape.support(-normal) - shape.center).dot(normal);
+            // just touching, and far below the surface
+            for depth in [0.1, 5.] {
+                let mut shape = shape.clone();
+                shape.center = plane.center + normal * (lowest.abs() - depth);
+
+                let (found, found_depth, point_a, point_b) = shape_contact(&plane, &shape).unwrap();
+                assert!(found.abs_diff_eq(normal, 1e-4), "normal {found:?}");
+                assert!((found_depth - depth).abs() < 1e-4, "depth {found_depth}");
+                assert!((point_a - plane.center).dot(normal).abs() < 1e-4);
+                assert!(point_b.abs_diff_eq(point_a - normal * depth, 1e-4));
+
+                let (flipped, _, _, _) = shape_contact(&shape, &plane).unwrap();
+                assert_eq!(flipped, -found);
+            }
+
+            let mut shape = shape.clone();
+            shape.center = plane.center + normal * (lowest.abs() + 0.01);
+            assert!(shape_contact(&plane, &shape).is_none());
+        }
+    }
+
+    #[test]
+    fn tilted_capsule_through_a_thin_box_is_pushed_out_of_it() {
+        let slab = Collider::from_cuboid(Vec3::new(2., 0.05, 2.), Vec3::ZERO, Quat::IDENTITY);
+        for tilt in [0.3f32, 0.6, 0.9] {
+            let mut capsule = Collider::capsule(1., 0.2);
+            capsule.rotation = Quat::from_rotation_z(tilt);
+            capsule.center = Vec3::new(0.1, 0.3, -0.2);
+
+            let (normal, depth, _, _) =
+                shape_contact(&capsule, &slab).expect("the capsule pierces the slab");
+            // lifting it clear of the top face is the shortest way out
+            let expected = 0.05 + tilt.cos() + 0.2 - 0.3;
+            assert!(normal.abs_diff_eq(Vec3::NEG_Y, 1e-3), "normal {normal:?}");
+            assert!((depth - expected).abs() < 1e-3, "depth {depth}");
+
+            capsule.center -= normal * (depth + 1e-3);
+            assert!(shape_contact(&capsule, &slab).is_none());
+        }
+    }
+
+    #[test]
+    fn ellipsoids_touch_along_their_stretched_surface() {
+        let floor =
+            Collider::from_cuboid(Vec3::new(5., 0.5, 5.), Vec3::NEG_Y * 0.5, Quat::IDENTITY);
+        let mut ellipsoid = Collider::ellipsoid(Vec3::new(1., 0.5, 0.25));
+        for (rotation, height) in [
+            (Quat::IDENTITY, 0.5),
+            (Quat::from_rotation_z(FRAC_PI_2), 1.),
+            (Quat::from_rotation_x(FRAC_PI_2), 0.25),
+        ] {
+            ellipsoid.rotation = rotation;
+            ellipsoid.center = Vec3::Y * (height - 0.1);
+
+            let (normal, depth, _, point_b) =
+                shape_contact(&ellipsoid, &floor).expect("the ellipsoid sinks 0.1 into the floor");
+            assert!(normal.abs_diff_eq(Vec3::NEG_Y, 1e-3), "normal {normal:?}");
+            assert!((depth - 0.1).abs() < 1e-3, "depth {depth}");
+            assert!(
+                point_b.xz().abs_diff_eq(Vec2::ZERO, 1e-2),
+                "point {point_b:?}"
+            );
+        }
+
+        // side by side with a sphere, the long axis reaches out to it
+        ellipsoid.rotation = Quat::IDENTITY;
+        ellipsoid.center = Vec3::ZERO;
+        let mut sphere = Collider::sphere(0.5);
+        sphere.center = Vec3::X * 1.4;
+        // EPA only approximates a contact between two curved surfaces
+        let (normal, depth, _, _) = shape_contact(&ellipsoid, &sphere).unwrap();
+        assert!(normal.abs_diff_eq(Vec3::X, 2e-2), "normal {normal:?}");
+        assert!((depth - 0.1).abs() < 2e-3, "depth {depth}");
+
+        sphere.center = Vec3::Y * 1.1;
+        assert!(shape_contact(&ellipsoid, &sphere).is_none());
+    }
+
+    #[test]
+    fn layouts_compare_shared_shapes_by_pointer() {
+        let corners = vec![Vec3::ZERO, Vec3::X, Vec3::Z];
+        let mesh = Collider::trimesh(corners.clone(), vec![[0, 2, 1]]);
+        let layout = |mesh: &Collider| {
+            Collider::compound(vec![
+                (Vec3::Y, Quat::IDENTITY, mesh.clone()),
+                (Vec3::X, Quat::IDENTITY, Collider::sphere(0.5)),
+            ])
+        };
+
+        assert!(same_layout(&layout(&mesh), &layout(&mesh)));
+        // an equal mesh built anew is another shape
+        let rebuilt = Collider::trimesh(corners, vec![[0, 2, 1]]);
+        assert!(!same_layout(&layout(&mesh), &layout(&rebuilt)));
+    }
+
+    #[test]
+    fn only_sensor_children_of_a_compound_are_sensors() {
+        let cuboid = Collider::from_cuboid(Vec3::splat(0.5), Vec3::ZERO, Quat::IDENTITY);
+        let mut sensor = cuboid.clone();
+        sensor.is_sensor = true;
+        let compound = Collider::compound(vec![
+            (Vec3::X * -0.6, Quat::IDENTITY, cuboid),
+            (Vec3::X * 0.6, Quat::IDENTITY, sensor),
+        ]);
+        assert!(!compound.is_sensor);
+
+        let mut other =
+            Collider::from_cuboid(Vec3::new(1., 0.5, 0.5), Vec3::Y * 0.9, Quat::IDENTITY);
+        let infos = get_collision_info(&compound, &Velocity::ZERO, &other, &Velocity::ZERO);
+        assert_eq!(infos.len(), 2);
+        for info in &infos {
+            let sensor =
+                is_sensor_part(&compound, info.child_a) || is_sensor_part(&other, info.child_b);
+            assert_eq!(sensor, info.child_a == Some(1));
+        }
+
+        other.is_sensor = true;
+        assert!(infos
+            .iter()
+            .all(|info| is_sensor_part(&other, info.child_b)));
+    }
 }
@@ -0,0 +1,149 @@
+use super::events::{pair_key, EntityPair};
+use super::ContactInfo;
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+/// Two solid bodies touching during the last physics step.
+#[derive(Clone, Debug)]
+pub struct Contact {
+    pub entity_a: Entity,
+    pub entity_b: Entity,
+    /// Contact between the two, with the normal pointing from `entity_a` to `entity_b`. It
+    /// reports the deepest point, and its manifold holds the points of every touching child shape.
+    pub info: ContactInfo,
+    /// Total impulse the solver applied along the normal to push the bodies apart.
+    pub impulse: f32,
+}
+
+impl Contact {
+    /// The entity on the other side of the contact from `entity`.
+    pub fn other(&self, entity: Entity) -> Entity {
+        if entity == self.entity_a {
+            self.entity_b
+        } else {
+            self.entity_a
+        }
+    }
+
+    /// Contact normal pointing away from `entity`.
+    pub fn normal_from(&self, entity: Entity) -> Vec3 {
+        if entity == self.entity_a {
+            self.info.normal
+        } else {
+            -self.info.normal
+        }
+    }
+
+    /// Contact point on the surface of `entity`.
+    pub fn point_on(&self, entity: Entity) -> Vec3 {
+        if entity == self.entity_a {
+            self.info.contact_point_a
+        } else {
+            self.info.contact_point_b
+        }
+    }
+}
+
+/// Every pair of solid bodies touching during the last physics step, looked up by entity.
+#[derive(Resource, Default)]
+pub struct Contacts {
+    pairs: HashMap<EntityPair, Contact>,
+    by_entity: HashMap<Entity, Vec<EntityPair>>,
+}
+
+impl Contacts {
+    /// Contacts involving `entity`.
+    pub fn of(&self, entity: Entity) -> impl Iterator<Item = &Contact> + '_ {
+        self.by_entity
+            .get(&entity)
+            .into_iter()
+            .flatten()
+            .map(|pair| &self.pairs[pair])
+    }
+
+    /// Entities touching `entity`.
+    pub fn touching(&self, entity: Entity) -> impl Iterator<Item = Entity> + '_ {
+        self.of(entity).map(move |contact| contact.other(entity))
+    }
+
+    /// The contact between `a` and `b`, whichever way round they touched.
+    pub fn between(&self, a: Entity, b: Entity) -> Option<&Contact> {
+        self.pairs.get(&pair_key(a, b))
+    }
+
+    pub fn are_touching(&self, a: Entity, b: Entity) -> bool {
+        self.pairs.contains_key(&pair_key(a, b))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Contact> {
+        self.pairs.values()
+    }
+
+    pub(crate) fn get_mut(&mut self, a: Entity, b: Entity) -> Option<&mut Contact> {
+        self.pairs.get_mut(&pair_key(a, b))
+    }
+
+    pub(crate) fn insert(&mut self, contact: Contact) {
+        let pair = pair_key(contact.entity_a, contact.entity_b);
+        if self.pairs.insert(pair, contact).is_none() {
+            self.by_entity.entry(pair.0).or_default().push(pair);
+            self.by_entity.entry(pair.1).or_default().push(pair);
+        }
+    }
+
+    /// Empties the graph, handing back the contacts of the previous step.
+    pub(crate) fn take(&mut self) -> HashMap<EntityPair, Contact> {
+        self.by_entity.clear();
+        std::mem::take(&mut self.pairs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+    use crate::testing::{dynamic, ground, physics_app, run, spawn};
+
+    #[test]
+    fn contacts_are_found_from_either_body() {
+        let mut app = physics_app();
+        let ground = spawn(&mut app, ground());
+        let cuboid = Collider::from_cuboid(Vec3::splat(0.5), Vec3::Y * 0.5, Quat::IDENTITY);
+        let crate_ = spawn(&mut app, dynamic(cuboid));
+        let mut ball = dynamic(Collider::sphere(0.25));
+        ball.collider.center = Vec3::Y * 1.25;
+        let ball = spawn(&mut app, ball);
+        run(&mut app, 20);
+
+        let contacts = app.world().resource::<Contacts>();
+        let mut touching: Vec<Entity> = contacts.touching(crate_).collect();
+        touching.sort_unstable();
+        assert_eq!(touching, [ground.min(ball), ground.max(ball)]);
+        assert_eq!(contacts.touching(ground).collect::<Vec<_>>(), [crate_]);
+        assert_eq!(contacts.of(ball).count(), 1);
+        assert_eq!(contacts.iter().count(), 2);
+        assert!(!contacts.are_touching(ground, ball));
+        assert!(contacts.between(ball, ground).is_none());
+
+        for (below, above, height) in [(ground, crate_, 0.), (crate_, ball, 1.)] {
+            assert!(contacts.are_touching(above, below));
+            let contact = contacts.between(above, below).unwrap();
+            assert_eq!(contact.other(below), above);
+            assert_eq!(contact.other(above), below);
+            assert!(contact.normal_from(below).abs_diff_eq(Vec3::Y, 1e-3));
+            assert!(contact.normal_from(above).abs_diff_eq(Vec3::NEG_Y, 1e-3));
+            assert!((contact.point_on(below).y - height).abs() < 0.02);
+            assert!((contact.point_on(above).y - height).abs() < 0.02);
+            assert!(contact.impulse > 0.);
+        }
+        // the lower contact carries the weight of both bodies
+        let impulse = |a, b| contacts.between(a, b).unwrap().impulse;
+        assert!(impulse(ground, crate_) > impulse(crate_, ball) * 1.5);
+
+        app.world_mut().despawn(ball);
+        run(&mut app, 1);
+        let contacts = app.world().resource::<Contacts>();
+        assert_eq!(contacts.touching(crate_).collect::<Vec<_>>(), [ground]);
+        assert_eq!(contacts.of(ball).count(), 0);
+    }
+}
@@ -0,0 +1,344 @@
+use super::mesh_positions;
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+/// How far a point has to be above a face to count as outside it, relative to the cloud size.
+const TOLERANCE: f64 = 1e-9;
+
+/// A convex polyhedron in collider local space, built with quickhull.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConvexHull {
+    pub points: Vec<Vec3>,
+    /// Triangles wound counter-clockwise when seen from outside.
+    pub faces: Vec<[usize; 3]>,
+    pub normals: Vec<Vec3>,
+    pub edges: Vec<[usize; 2]>,
+    pub volume: f32,
+    pub center_of_mass: Vec3,
+    /// Inertia tensor of the hull with unit mass, about its center of mass.
+    pub inertia: Mat3,
+}
+
+/// A hull face with its plane in double precision.
+struct Face {
+    indices: [usize; 3],
+    normal: DVec3,
+    offset: f64,
+    outside: Vec<usize>,
+}
+
+impl Face {
+    fn new(points: &[DVec3], indices: [usize; 3]) -> Self {
+        let [a, b, c] = indices.map(|index| points[index]);
+        let normal = (b - a).cross(c - a).normalize_or_zero();
+
+        Self {
+            indices,
+            normal,
+            offset: normal.dot(a),
+            outside: vec![],
+        }
+    }
+
+    fn distance(&self, point: DVec3) -> f64 {
+        self.normal.dot(point) - self.offset
+    }
+
+    fn edges(&self) -> [(usize, usize); 3] {
+        let [a, b, c] = self.indices;
+        [(a, b), (b, c), (c, a)]
+    }
+}
+
+impl ConvexHull {
+    /// Computes the convex hull of a point cloud. Returns `None` when the points are coplanar.
+    pub fn new(input: &[Vec3]) -> Option<Self> {
+        let points: Vec<DVec3> = input.iter().map(|point| point.as_dvec3()).collect();
+        let points = points.as_slice();
+        let (min, max) = points.iter().fold(
+            (DVec3::splat(f64::MAX), DVec3::splat(f64::MIN)),
+            |(min, max), &point| (min.min(point), max.max(point)),
+        );
+        let epsilon = (max - min).length() * TOLERANCE;
+
+        let [i0, mut i1, mut i2, i3] = initial_simplex(points, epsilon)?;
+        if (points[i1] - points[i0])
+            .cross(points[i2] - points[i0])
+            .dot(points[i3] - points[i0])
+            > 0.0
+        {
+            std::mem::swap(&mut i1, &mut i2);
+        }
+
+        let mut faces: Vec<Option<Face>> = vec![];
+        // directed edge -> face on its left, used to walk across neighbouring faces
+        let mut edge_faces: HashMap<(usize, usize), usize> = HashMap::new();
+        for indices in [[i0, i1, i2], [i0, i3, i1], [i1, i3, i2], [i2, i3, i0]] {
+            add_face(points, &mut faces, &mut edge_faces, indices);
+        }
+
+        let candidates: Vec<usize> = (0..points.len())
+            .filter(|index| ![i0, i1, i2, i3].contains(index))
+            .collect();
+        assign_outside(points, &mut faces, &[0, 1, 2, 3], candidates, epsilon);
+
+        while let Some(start) = faces
+            .iter()
+            .position(|face| face.as_ref().is_some_and(|face| !face.outside.is_empty()))
+        {
+            let face = faces[start].as_ref().unwrap();
+            let apex = face
+                .outside
+                .iter()
+                .copied()
+                .max_by(|&x, &y| {
+                    face.distance(points[x])
+                        .total_cmp(&face.distance(points[y]))
+                })
+                .unwrap();
+
+            // flood the faces visible from the apex, collecting the horizon around them
+            let mut visible = vec![start];
+            let mut horizon = vec![];
+            let mut stack = vec![start];
+            while let Some(current) = stack.pop() {
+                for (from, to) in faces[current].as_ref().unwrap().edges() {
+                    let neighbour = edge_faces[&(to, from)];
+                    if visible.contains(&neighbour) {
+                        continue;
+                    }
+                    if faces[neighbour].as_ref().unwrap().distance(points[apex]) > epsilon {
+                        visible.push(neighbour);
+                        stack.push(neighbour);
+                    } else {
+                        horizon.push((from, to));
+                    }
+                }
+            }
+
+            let mut orphans = vec![];
+            for index in visible {
+                let face = faces[index].take().unwrap();
+                for edge in face.edges() {
+                    edge_faces.remove(&edge);
+                }
+                orphans.extend(face.outside.into_iter().filter(|&point| point != apex));
+            }
+
+            let mut targets: Vec<usize> = horizon
+                .into_iter()
+                .map(|(from, to)| add_face(points, &mut faces, &mut edge_faces, [from, to, apex]))
+                .collect();
+            // orphans no new face claims may still lie above an older one
+            let cone = targets.len();
+            targets.extend((0..faces.len() - cone).filter(|&index| faces[index].is_some()));
+            assign_outside(points, &mut faces, &targets, orphans, epsilon);
+        }
+
+        let faces: Vec<Face> = faces.into_iter().flatten().collect();
+
+        // keep only the points referenced by the final faces
+        let mut remap = vec![usize::MAX; points.len()];
+        let mut hull_points = vec![];
+        let hull_faces: Vec<[usize; 3]> = faces
+            .iter()
+            .map(|face| {
+                face.indices.map(|index| {
+                    if remap[index] == usize::MAX {
+                        remap[index] = hull_points.len();
+                        hull_points.push(input[index]);
+                    }
+                    remap[index]
+                })
+            })
+            .collect();
+
+        let mut edges: Vec<[usize; 2]> = hull_faces
+            .iter()
+            .flat_map(|&[a, b, c]| [[a, b], [b, c], [c, a]])
+            .map(|[from, to]| [from.min(to), from.max(to)])
+            .collect();
+        edges.sort_unstable();
+        edges.dedup();
+
+        let (volume, center_of_mass, inertia) = mass_properties(&hull_points, &hull_faces);
+
+        Some(Self {
+            normals: faces.iter().map(|face| face.normal.as_vec3()).collect(),
+            points: hull_points,
+            faces: hull_faces,
+            edges,
+            volume,
+            center_of_mass,
+            inertia,
+        })
+    }
+
+    /// Computes the convex hull of the vertex positions of a mesh.
+    pub fn from_mesh(mesh: &Mesh) -> Option<Self> {
+        Self::new(&mesh_positions(mesh)?)
+    }
+
+    /// Furthest hull point in `direction`, in local space.
+    pub fn support(&self, direction: Vec3) -> Vec3 {
+        self.points
+            .iter()
+            .copied()
+            .max_by(|a, b| a.dot(direction).total_cmp(&b.dot(direction)))
+            .unwrap_or(Vec3::ZERO)
+    }
+}
+
+fn add_face(
+    points: &[DVec3],
+    faces: &mut Vec<Option<Face>>,
+    edge_faces: &mut HashMap<(usize, usize), usize>,
+    indices: [usize; 3],
+) -> usize {
+    let face = Face::new(points, indices);
+    for edge in face.edges() {
+        edge_faces.insert(edge, faces.len());
+    }
+    faces.push(Some(face));
+    faces.len() - 1
+}
+
+fn assign_outside(
+    points: &[DVec3],
+    faces: &mut [Option<Face>],
+    targets: &[usize],
+    candidates: Vec<usize>,
+    epsilon: f64,
+) {
+    for index in candidates {
+        if let Some(face) = targets
+            .iter()
+            .filter_map(|&target| faces[target].as_ref())
+            .position(|face| face.distance(points[index]) > epsilon)
+        {
+            faces[targets[face]].as_mut().unwrap().outside.push(index);
+        }
+    }
+}
+
+fn initial_simplex(points: &[DVec3], epsilon: f64) -> Option<[usize; 4]> {
+    let furthest_from = |score: &dyn Fn(DVec3) -> f64| {
+        (0..points.len()).max_by(|&a, &b| score(points[a]).total_cmp(&score(points[b])))
+    };
+
+    let i0 = furthest_from(&|point| point.x)?;
+    let i1 = furthest_from(&|point| point.distance_squared(points[i0]))?;
+    let axis = (points[i1] - points[i0]).normalize_or_zero();
+    let i2 = furthest_from(&|point| (point - points[i0]).cross(axis).length_squared())?;
+    let normal = (points[i1] - points[i0])
+        .cross(points[i2] - points[i0])
+        .normalize_or_zero();
+    let i3 = furthest_from(&|point| (point - points[i0]).dot(normal).abs())?;
+
+    if points[i0].distance(points[i1]) <= epsilon
+        || (points[i2] - points[i0]).cross(axis).length() <= epsilon
+        || (points[i3] - points[i0]).dot(normal).abs() <= epsilon
+    {
+        return None;
+    }
+
+    Some([i0, i1, i2, i3])
+}
+
+/// Volume, center of mass and unit mass inertia tensor about the center of mass of a closed,
+/// outward wound triangle mesh, summed over tetrahedra spanned with the origin.
+fn mass_properties(points: &[Vec3], faces: &[[usize; 3]]) -> (f32, Vec3, Mat3) {
+    let outer = |a: Vec3, b: Vec3| Mat3::from_cols(a * b.x, a * b.y, a * b.z);
+
+    let mut volume = 0.0;
+    let mut first_moment = Vec3::ZERO;
+    let mut covariance = Mat3::ZERO;
+    for &[i, j, k] in faces {
+        let (a, b, c) = (points[i], points[j], points[k]);
+        let tetrahedron_volume = a.dot(b.cross(c)) / 6.0;
+        let sum = a + b + c;
+
+        volume += tetrahedron_volume;
+        first_moment += sum * (tetrahedron_volume / 4.0);
+        covariance += (outer(a, a) + outer(b, b) + outer(c, c) + outer(sum, sum))
+            * (tetrahedron_volume / 20.0);
+    }
+
+    if volume <= f32::EPSILON {
+        return (0.0, Vec3::ZERO, Mat3::ZERO);
+    }
+
+    let center_of_mass = first_moment / volume;
+    // move the second moment to the center of mass and normalise to unit mass
+    let covariance = (covariance - outer(center_of_mass, center_of_mass) * volume) * (1.0 / volume);
+    let trace = covariance.x_axis.x + covariance.y_axis.y + covariance.z_axis.z;
+    let inertia = Mat3::from_diagonal(Vec3::splat(trace)) - covariance;
+
+    (volume, center_of_mass, inertia)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Lcg;
+
+    /// Between 20 and 520 points spread through the box of `scale`.
+    fn cloud(random: &mut Lcg, scale: Vec3) -> Vec<Vec3> {
+        let count = 20 + ((random.next() + 1.0) * 250.0) as usize;
+        (0..count).map(|_| random.vec3() * scale).collect()
+    }
+
+    /// Checks that every hull point and every input point lies below every face.
+    fn assert_convex_and_containing(hull: &ConvexHull, input: &[Vec3]) {
+        for (face, normal) in hull.faces.iter().zip(&hull.normals) {
+            let offset = normal.dot(hull.points[face[0]]);
+            for point in &hull.points {
+                assert!(normal.dot(*point) - offset < 1e-5, "hull folds inwards");
+            }
+            for point in input {
+                assert!(normal.dot(*point) - offset < 1e-5, "input point outside");
+            }
+        }
+    }
+
+    #[test]
+    fn sphere_clouds() {
+        let mut random = Lcg(1);
+        for _ in 0..100 {
+            let points: Vec<Vec3> = cloud(&mut random, Vec3::ONE)
+                .into_iter()
+                .map(|point| point.normalize_or_zero())
+                .collect();
+            let hull = ConvexHull::new(&points).unwrap();
+            assert_convex_and_containing(&hull, &points);
+        }
+    }
+
+    #[test]
+    fn thin_slabs() {
+        let mut random = Lcg(2);
+        for _ in 0..100 {
+            let points = cloud(&mut random, Vec3::new(1.0, 0.001, 1.0));
+            let hull = ConvexHull::new(&points).unwrap();
+            assert_convex_and_containing(&hull, &points);
+        }
+    }
+
+    #[test]
+    fn grid_keeps_only_corners() {
+        let points: Vec<Vec3> = (0..125)
+            .map(|index| {
+                Vec3::new(
+                    (index % 5) as f32,
+                    (index / 5 % 5) as f32,
+                    (index / 25) as f32,
+                )
+            })
+            .collect();
+        let hull = ConvexHull::new(&points).unwrap();
+        assert_convex_and_containing(&hull, &points);
+        assert_eq!(hull.points.len(), 8);
+        assert!((hull.volume - 64.0).abs() < 1e-3);
+    }
+}
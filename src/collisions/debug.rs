@@ -0,0 +1,147 @@
+use super::{Collider, ColliderShape, Triangle};
+use crate::bodies::{RigidbodyComponent, RigidbodyType};
+use bevy::prelude::*;
+use std::f32::consts::{PI, TAU};
+
+const CIRCLE_SEGMENTS: usize = 24;
+
+/// Draws the collider of every rigid body as a gizmo wireframe.
+pub struct ColliderDebugPlugin;
+impl Plugin for ColliderDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, draw_colliders);
+    }
+}
+
+fn draw_colliders(query: Query<&RigidbodyComponent>, mut gizmos: Gizmos) {
+    for body in query.iter() {
+        let color = match body.rbt {
+            RigidbodyType::Static => Color::srgb(0.6, 0.6, 0.6),
+            RigidbodyType::Dynamic => Color::srgb(0.2, 0.9, 0.3),
+            RigidbodyType::Kinematic => Color::srgb(0.3, 0.6, 1.0),
+        };
+
+        for (start, end) in body.collider.wireframe() {
+            gizmos.line(start, end, color);
+        }
+    }
+}
+
+impl Collider {
+    /// World space line segments outlining the collider.
+    pub fn wireframe(&self) -> Vec<(Vec3, Vec3)> {
+        let (r, hh) = (self.radius, self.half_height);
+        let mut lines = vec![];
+
+        match &self.collider_shape {
+            ColliderShape::Cuboid => {
+                let corner = |i: usize| {
+                    Vec3::new(
+                        if i & 1 == 0 { -1. } else { 1. },
+                        if i & 2 == 0 { -1. } else { 1. },
+                        if i & 4 == 0 { -1. } else { 1. },
+                    ) * self.half_extents
+                };
+                for i in 0..8 {
+                    for bit in [1, 2, 4] {
+                        if i & bit == 0 {
+                            lines.push((corner(i), corner(i | bit)));
+                        }
+                    }
+                }
+            }
+            ColliderShape::Sphere | ColliderShape::Ellipsoid => {
+                for (u, v) in [(Vec3::X, Vec3::Y), (Vec3::Y, Vec3::Z), (Vec3::Z, Vec3::X)] {
+                    arc(&mut lines, Vec3::ZERO, u, v, TAU);
+                }
+                for line in &mut lines {
+                    *line = (line.0 * self.half_extents, line.1 * self.half_extents);
+                }
+            }
+            ColliderShape::Capsule => {
+                for y in [-hh, hh] {
+                    arc(&mut lines, Vec3::Y * y, Vec3::X * r, Vec3::Z * r, TAU);
+                }
+                for side in [Vec3::X, Vec3::Z] {
+                    arc(&mut lines, Vec3::Y * hh, side * r, Vec3::Y * r, PI);
+                    arc(&mut lines, Vec3::Y * -hh, side * r, Vec3::NEG_Y * r, PI);
+                }
+                for side in [Vec3::X, Vec3::NEG_X, Vec3::Z, Vec3::NEG_Z] {
+                    lines.push((side * r - Vec3::Y * hh, side * r + Vec3::Y * hh));
+                }
+            }
+            ColliderShape::Cylinder => {
+                for y in [-hh, hh] {
+                    arc(&mut lines, Vec3::Y * y, Vec3::X * r, Vec3::Z * r, TAU);
+                }
+                for side in [Vec3::X, Vec3::NEG_X, Vec3::Z, Vec3::NEG_Z] {
+                    lines.push((side * r - Vec3::Y * hh, side * r + Vec3::Y * hh));
+                }
+            }
+            ColliderShape::Cone => {
+                arc(&mut lines, Vec3::Y * -hh, Vec3::X * r, Vec3::Z * r, TAU);
+                for side in [Vec3::X, Vec3::NEG_X, Vec3::Z, Vec3::NEG_Z] {
+                    lines.push((side * r - Vec3::Y * hh, Vec3::Y * hh));
+                }
+            }
+            ColliderShape::ConvexHull(hull) => {
+                for &[a, b] in &hull.edges {
+                    lines.push((hull.points[a], hull.points[b]));
+                }
+            }
+            ColliderShape::HalfSpace { normal } => {
+                // a patch of the surface with the normal sticking out of its middle
+                let (u, v) = normal.any_orthonormal_pair();
+                for step in -5..=5 {
+                    let offset = step as f32;
+                    lines.push((u * offset - v * 5., u * offset + v * 5.));
+                    lines.push((v * offset - u * 5., v * offset + u * 5.));
+                }
+                lines.push((Vec3::ZERO, *normal));
+            }
+            ColliderShape::Compound(children) => {
+                for child in children.iter() {
+                    lines.extend(child.wireframe());
+                }
+            }
+            ColliderShape::TriMesh(trimesh) => {
+                for index in 0..trimesh.indices.len() {
+                    triangle_edges(&mut lines, trimesh.triangle(index));
+                }
+            }
+            ColliderShape::HeightField(field) => {
+                for index in 0..(field.rows() - 1) * (field.columns() - 1) * 2 {
+                    triangle_edges(&mut lines, field.triangle(index));
+                }
+            }
+        }
+
+        lines
+            .into_iter()
+            .map(|(start, end)| {
+                (
+                    self.center + self.rotation * start,
+                    self.center + self.rotation * end,
+                )
+            })
+            .collect()
+    }
+}
+
+/// Arc around `center` starting at `center + u` and turning towards `center + v`.
+fn arc(lines: &mut Vec<(Vec3, Vec3)>, center: Vec3, u: Vec3, v: Vec3, angle: f32) {
+    let segments = (CIRCLE_SEGMENTS as f32 * angle / TAU).ceil() as usize;
+    let point = |i: usize| {
+        let (sin, cos) = (angle * i as f32 / segments as f32).sin_cos();
+        center + u * cos + v * sin
+    };
+    lines.extend((0..segments).map(|i| (point(i), point(i + 1))));
+}
+
+fn triangle_edges(lines: &mut Vec<(Vec3, Vec3)>, triangle: Triangle) {
+    lines.extend([
+        (triangle.a, triangle.b),
+        (triangle.b, triangle.c),
+        (triangle.c, triangle.a),
+    ]);
+}
@@ -0,0 +1,263 @@
+use super::Aabb;
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+/// How far leaf boxes reach past their collider, so small motions do not touch the tree.
+const MARGIN: f32 = 0.1;
+
+/// Dynamic bounding volume hierarchy broad phase. Leaves hold fattened boxes and are only
+/// reinserted once their collider leaves them; inserts descend towards the sibling that grows
+/// the tree's surface area the least.
+#[derive(Default)]
+pub struct DynamicTree {
+    nodes: Vec<TreeNode>,
+    free: Vec<usize>,
+    root: Option<usize>,
+    leaves: HashMap<Entity, usize>,
+    /// Tight boxes as of the last update.
+    aabbs: HashMap<Entity, Aabb>,
+    /// Colliders with infinite bounds, like half-spaces, kept out of the tree and paired with
+    /// everything.
+    unbounded: Vec<Entity>,
+}
+
+struct TreeNode {
+    aabb: Aabb,
+    parent: Option<usize>,
+    kind: NodeKind,
+}
+
+enum NodeKind {
+    Leaf(Entity),
+    Branch(usize, usize),
+}
+
+impl DynamicTree {
+    /// Replaces the tracked boxes with this frame's ones, refitting only the leaves whose
+    /// collider moved out of its fat box.
+    pub fn update(&mut self, aabbs: impl IntoIterator<Item = (Entity, Aabb)>) {
+        self.aabbs = aabbs.into_iter().collect();
+
+        let removed: Vec<Entity> = self
+            .leaves
+            .keys()
+            .filter(|entity| !self.aabbs.contains_key(entity))
+            .copied()
+            .collect();
+        for entity in removed {
+            self.remove(entity);
+        }
+
+        self.unbounded.clear();
+        let aabbs: Vec<(Entity, Aabb)> = self.aabbs.iter().map(|(&e, &a)| (e, a)).collect();
+        for (entity, aabb) in aabbs {
+            if aabb.is_unbounded() {
+                self.remove(entity);
+                self.unbounded.push(entity);
+                continue;
+            }
+            if let Some(&leaf) = self.leaves.get(&entity) {
+                if self.nodes[leaf].aabb.contains(&aabb) {
+                    continue;
+                }
+                self.remove(entity);
+            }
+            self.insert(entity, aabb.expanded(MARGIN));
+        }
+    }
+
+    /// Overlapping pairs, found by querying the tree with every bounded box.
+    pub fn pairs(&self) -> Vec<(Entity, Entity)> {
+        let mut pairs = vec![];
+        for (&entity, aabb) in &self.aabbs {
+            if aabb.is_unbounded() {
+                continue;
+            }
+            self.traverse(
+                |node| node.intersects(aabb),
+                |other| {
+                    if entity < other && self.aabbs[&other].intersects(aabb) {
+                        pairs.push((entity, other));
+                    }
+                },
+            );
+        }
+        for (i, &entity) in self.unbounded.iter().enumerate() {
+            pairs.extend(
+                self.aabbs
+                    .keys()
+                    .filter(|&&other| other != entity && !self.unbounded[..i].contains(&other))
+                    .map(|&other| (entity, other)),
+            );
+        }
+        pairs
+    }
+
+    /// Calls `visit` with every entity whose fat box overlaps `aabb`, including unbounded ones.
+    pub fn query(&self, aabb: &Aabb, mut visit: impl FnMut(Entity)) {
+        self.unbounded.iter().copied().for_each(&mut visit);
+        self.traverse(|node| node.intersects(aabb), visit);
+    }
+
+    /// Calls `visit` with every entity whose fat box is crossed by the ray within
+    /// `max_distance`, including unbounded ones.
+    pub fn query_ray(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+        mut visit: impl FnMut(Entity),
+    ) {
+        self.unbounded.iter().copied().for_each(&mut visit);
+        self.traverse(
+            |node| node.ray_hit(origin, direction, max_distance).is_some(),
+            visit,
+        );
+    }
+
+    fn traverse(&self, enter: impl Fn(&Aabb) -> bool, mut visit: impl FnMut(Entity)) {
+        let mut stack: Vec<usize> = self.root.into_iter().collect();
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !enter(&node.aabb) {
+                continue;
+            }
+            match node.kind {
+                NodeKind::Leaf(entity) => visit(entity),
+                NodeKind::Branch(left, right) => stack.extend([left, right]),
+            }
+        }
+    }
+
+    fn allocate(&mut self, node: TreeNode) -> usize {
+        match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn insert(&mut self, entity: Entity, aabb: Aabb) {
+        let leaf = self.allocate(TreeNode {
+            aabb,
+            parent: None,
+            kind: NodeKind::Leaf(entity),
+        });
+        self.leaves.insert(entity, leaf);
+
+        let Some(mut sibling) = self.root else {
+            self.root = Some(leaf);
+            return;
+        };
+
+        // walk down while pushing the leaf into a child is cheaper than pairing it here
+        while let NodeKind::Branch(left, right) = self.nodes[sibling].kind {
+            let area = self.nodes[sibling].aabb.area();
+            let combined = self.nodes[sibling].aabb.merge(&aabb).area();
+            let cost = 2.0 * combined;
+            let inheritance = 2.0 * (combined - area);
+            let child_cost = |child: usize| {
+                let node = &self.nodes[child];
+                let grown = node.aabb.merge(&aabb).area();
+                match node.kind {
+                    NodeKind::Leaf(_) => grown + inheritance,
+                    NodeKind::Branch(..) => grown - node.aabb.area() + inheritance,
+                }
+            };
+            let (left_cost, right_cost) = (child_cost(left), child_cost(right));
+            if cost < left_cost && cost < right_cost {
+                break;
+            }
+            sibling = if left_cost < right_cost { left } else { right };
+        }
+
+        let old_parent = self.nodes[sibling].parent;
+        let parent = self.allocate(TreeNode {
+            aabb: self.nodes[sibling].aabb.merge(&aabb),
+            parent: old_parent,
+            kind: NodeKind::Branch(sibling, leaf),
+        });
+        self.nodes[sibling].parent = Some(parent);
+        self.nodes[leaf].parent = Some(parent);
+        match old_parent {
+            Some(old_parent) => self.replace_child(old_parent, sibling, parent),
+            None => self.root = Some(parent),
+        }
+        self.refit(old_parent);
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        let Some(leaf) = self.leaves.remove(&entity) else {
+            return;
+        };
+        self.free.push(leaf);
+
+        let Some(parent) = self.nodes[leaf].parent else {
+            self.root = None;
+            return;
+        };
+        let NodeKind::Branch(left, right) = self.nodes[parent].kind else {
+            unreachable!("tree parents are always branches");
+        };
+        let sibling = if left == leaf { right } else { left };
+        let grandparent = self.nodes[parent].parent;
+        self.free.push(parent);
+        self.nodes[sibling].parent = grandparent;
+        match grandparent {
+            Some(grandparent) => self.replace_child(grandparent, parent, sibling),
+            None => self.root = Some(sibling),
+        }
+        self.refit(grandparent);
+    }
+
+    fn replace_child(&mut self, parent: usize, old: usize, new: usize) {
+        if let NodeKind::Branch(left, right) = &mut self.nodes[parent].kind {
+            if *left == old {
+                *left = new;
+            } else {
+                *right = new;
+            }
+        }
+    }
+
+    /// Recomputes the boxes of `node` and all of its ancestors.
+    fn refit(&mut self, mut node: Option<usize>) {
+        while let Some(index) = node {
+            if let NodeKind::Branch(left, right) = self.nodes[index].kind {
+                self.nodes[index].aabb = self.nodes[left].aabb.merge(&self.nodes[right].aabb);
+            }
+            node = self.nodes[index].parent;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collisions::Collider;
+    use crate::testing::{brute_force_pairs, drifting_boxes, normalized};
+
+    #[test]
+    fn pairs_match_brute_force() {
+        let mut tree = DynamicTree::default();
+        for frame in 0..40 {
+            let mut boxes = drifting_boxes(frame, 60);
+            // a half-space, which the tree keeps out of its nodes, on every other frame
+            if frame % 4 < 2 {
+                boxes.push((Entity::from_raw(100), Collider::half_space(Vec3::Y).aabb()));
+            }
+            tree.update(boxes.iter().copied());
+
+            assert_eq!(
+                normalized(tree.pairs()),
+                brute_force_pairs(&boxes),
+                "frame {frame}"
+            );
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use super::{ContactInfo, Contacts};
+use bevy::prelude::*;
+use std::collections::HashSet;
+
+/// Two solid bodies started touching.
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CollisionStarted(pub Entity, pub Entity);
+
+/// Two solid bodies stopped touching, or one of the two went away.
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CollisionEnded(pub Entity, pub Entity);
+
+/// Two solid bodies are still touching, with their deepest contact this step. The contact normal
+/// points from the first entity towards the second. Only sent when enabled through
+/// [`CollisionEventSettings`].
+#[derive(Event, Clone, Debug)]
+pub struct CollisionPersisted(pub Entity, pub Entity, pub ContactInfo);
+
+#[derive(Resource, Default)]
+pub struct CollisionEventSettings {
+    /// Send a [`CollisionPersisted`] every step for every pair that keeps touching.
+    pub persisted: bool,
+}
+
+/// A sensor started overlapping another body. The sensor is not necessarily the first entity.
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OverlapStarted(pub Entity, pub Entity);
+
+/// A sensor stopped overlapping another body, or one of the two went away.
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OverlapEnded(pub Entity, pub Entity);
+
+/// Two entities, smallest first when built by [`pair_key`].
+pub(crate) type EntityPair = (Entity, Entity);
+
+/// Pairs that touched during the last step and the ones touching during the current one, so
+/// that the end of a step can tell which pairs started or stopped touching.
+#[derive(Default)]
+pub(crate) struct PairTracker {
+    previous: HashSet<EntityPair>,
+    current: HashSet<EntityPair>,
+}
+
+impl PairTracker {
+    /// Whether the pair touched during the last step.
+    pub(crate) fn touched_before(&self, pair: &EntityPair) -> bool {
+        self.previous.contains(pair)
+    }
+
+    pub(crate) fn touch(&mut self, pair: EntityPair) {
+        self.current.insert(pair);
+    }
+
+    /// Ends the current step, returning the pairs that started and the pairs that ended
+    /// touching during it.
+    pub(crate) fn advance(&mut self) -> (Vec<EntityPair>, Vec<EntityPair>) {
+        let started = self.current.difference(&self.previous).copied().collect();
+        let ended = self.previous.difference(&self.current).copied().collect();
+        self.previous = std::mem::take(&mut self.current);
+        (started, ended)
+    }
+}
+
+/// The same key for a pair whichever way round it is given.
+pub(crate) fn pair_key(a: Entity, b: Entity) -> EntityPair {
+    if a < b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct SensorOverlaps(pub(crate) PairTracker);
+
+#[derive(Resource, Default)]
+pub(crate) struct SolidContacts(pub(crate) PairTracker);
+
+pub(crate) fn send_overlap_events(
+    mut sensor_overlaps: ResMut<SensorOverlaps>,
+    mut overlap_started: EventWriter<OverlapStarted>,
+    mut overlap_ended: EventWriter<OverlapEnded>,
+) {
+    let (started, ended) = sensor_overlaps.0.advance();
+    overlap_started.send_batch(started.into_iter().map(|(a, b)| OverlapStarted(a, b)));
+    overlap_ended.send_batch(ended.into_iter().map(|(a, b)| OverlapEnded(a, b)));
+}
+
+pub(crate) fn send_collision_events(
+    mut solid_contacts: ResMut<SolidContacts>,
+    contacts: Res<Contacts>,
+    settings: Res<CollisionEventSettings>,
+    mut collision_started: EventWriter<CollisionStarted>,
+    mut collision_ended: EventWriter<CollisionEnded>,
+    mut collision_persisted: EventWriter<CollisionPersisted>,
+) {
+    if settings.persisted {
+        collision_persisted.send_batch(
+            contacts
+                .iter()
+                .filter(|contact| {
+                    solid_contacts
+                        .0
+                        .touched_before(&pair_key(contact.entity_a, contact.entity_b))
+                })
+                .map(|contact| {
+                    CollisionPersisted(contact.entity_a, contact.entity_b, contact.info.clone())
+                }),
+        );
+    }
+
+    let (started, ended) = solid_contacts.0.advance();
+    collision_started.send_batch(started.into_iter().map(|(a, b)| CollisionStarted(a, b)));
+    collision_ended.send_batch(ended.into_iter().map(|(a, b)| CollisionEnded(a, b)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+    use crate::testing::{body_mut, dynamic, ground, physics_app, record, recorded, run, spawn};
+
+    #[test]
+    fn collisions_start_persist_and_end_once_per_touch() {
+        let mut app = physics_app();
+        app.insert_resource(CollisionEventSettings { persisted: true });
+        record::<CollisionStarted>(&mut app);
+        record::<CollisionEnded>(&mut app);
+        record::<CollisionPersisted>(&mut app);
+        let ground = spawn(&mut app, ground());
+        let mut ball = dynamic(Collider::sphere(0.5));
+        ball.collider.center = Vec3::Y * 0.45;
+        let ball = spawn(&mut app, ball);
+        let pair = pair_key(ground, ball);
+
+        run(&mut app, 20);
+        assert_eq!(
+            recorded::<CollisionStarted>(&app),
+            [CollisionStarted(pair.0, pair.1)]
+        );
+        assert!(recorded::<CollisionEnded>(&app).is_empty());
+        // every step after the first one it touched in
+        assert_eq!(recorded::<CollisionPersisted>(&app).len(), 19);
+
+        // lifted clear, the ball stops touching and lands again
+        let lifted = Vec3::Y * 2.;
+        app.world_mut()
+            .get_mut::<Transform>(ball)
+            .unwrap()
+            .translation = lifted;
+        body_mut(&mut app, ball).collider.center = lifted;
+        run(&mut app, 60);
+        assert_eq!(
+            recorded::<CollisionEnded>(&app),
+            [CollisionEnded(pair.0, pair.1)]
+        );
+        assert_eq!(recorded::<CollisionStarted>(&app).len(), 2);
+
+        app.world_mut().despawn(ball);
+        run(&mut app, 2);
+        assert_eq!(recorded::<CollisionEnded>(&app).len(), 2);
+        assert_eq!(recorded::<CollisionStarted>(&app).len(), 2);
+    }
+}
@@ -0,0 +1,81 @@
+use crate::bodies::{RigidBodyState, RigidbodyComponent, RigidbodyType};
+use bevy::prelude::*;
+
+type PairHook =
+    dyn Fn(Entity, &RigidbodyComponent, Entity, &RigidbodyComponent) -> bool + Send + Sync;
+
+/// Game specific rule deciding whether two bodies whose bounds overlap should collide. Runs
+/// after the built in filtering, so it only sees pairs that could produce a response.
+#[derive(Resource, Default)]
+pub struct PairFilter {
+    hook: Option<Box<PairHook>>,
+}
+
+impl PairFilter {
+    pub fn new(
+        hook: impl Fn(Entity, &RigidbodyComponent, Entity, &RigidbodyComponent) -> bool
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        Self {
+            hook: Some(Box::new(hook)),
+        }
+    }
+
+    pub fn allows(
+        &self,
+        entity_a: Entity,
+        a: &RigidbodyComponent,
+        entity_b: Entity,
+        b: &RigidbodyComponent,
+    ) -> bool {
+        self.hook
+            .as_ref()
+            .is_none_or(|hook| hook(entity_a, a, entity_b, b))
+    }
+}
+
+/// Bitmask collision filtering. Two bodies interact when the memberships of each share a bit
+/// with the filters of the other. Bodies without the component are in every group.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CollisionGroups {
+    pub memberships: u32,
+    pub filters: u32,
+}
+
+impl CollisionGroups {
+    pub const ALL: u32 = u32::MAX;
+    pub const NONE: u32 = 0;
+
+    pub fn new(memberships: u32, filters: u32) -> Self {
+        Self {
+            memberships,
+            filters,
+        }
+    }
+
+    pub fn interacts_with(&self, other: &Self) -> bool {
+        self.memberships & other.filters != 0 && other.memberships & self.filters != 0
+    }
+}
+
+impl Default for CollisionGroups {
+    fn default() -> Self {
+        Self::new(Self::ALL, Self::ALL)
+    }
+}
+
+/// Whether the body can be moved by a contact this frame.
+fn is_active(body: &RigidbodyComponent) -> bool {
+    body.rbt != RigidbodyType::Static && body.state == RigidBodyState::Awake
+}
+
+/// Only pairs with at least one awake, non static body can produce a response. Sensors keep
+/// reporting overlaps with sleeping bodies, so for them any non static body is enough.
+pub(crate) fn can_respond(a: &RigidbodyComponent, b: &RigidbodyComponent) -> bool {
+    if a.collider.is_sensor || b.collider.is_sensor {
+        return a.rbt != RigidbodyType::Static || b.rbt != RigidbodyType::Static;
+    }
+    is_active(a) || is_active(b)
+}
@@ -0,0 +1,735 @@
+use bevy::prelude::*;
+
+const MAX_ITERATIONS: usize = 64;
+const EPA_MAX_ITERATIONS: usize = 128;
+const EPA_TOLERANCE: f32 = 1e-4;
+const DISTANCE_TOLERANCE: f32 = 1e-5;
+
+/// A convex shape described by its support function, usable by GJK and EPA.
+pub trait SupportMap {
+    /// Furthest point of the shape in `direction`, in world space.
+    fn support(&self, direction: Vec3) -> Vec3;
+
+    /// Any point inside the shape, used to pick the initial search direction.
+    fn center(&self) -> Vec3;
+}
+
+/// Result of a penetration query between two overlapping shapes.
+#[derive(Clone, Copy, Debug)]
+pub struct Penetration {
+    /// Points from the first shape towards the second.
+    pub normal: Vec3,
+    pub depth: f32,
+    /// Deepest point of the first shape inside the second.
+    pub point_a: Vec3,
+    /// Deepest point of the second shape inside the first.
+    pub point_b: Vec3,
+}
+
+/// A vertex of the Minkowski difference together with the support points it came from.
+#[derive(Clone, Copy, Debug)]
+struct SupportPoint {
+    point: Vec3,
+    a: Vec3,
+    b: Vec3,
+}
+
+fn same_direction(a: Vec3, b: Vec3) -> bool {
+    a.dot(b) > 0.0
+}
+
+/// Like [`same_direction`], but treats nearly perpendicular vectors as not pointing the same way,
+/// so an origin lying on a face counts as enclosed instead of cycling between simplices.
+fn clearly_same_direction(a: Vec3, b: Vec3) -> bool {
+    a.dot(b) > 1e-6 * a.length() * b.length()
+}
+
+/// Support point of the Minkowski difference `a - b`.
+fn support<A: SupportMap + ?Sized, B: SupportMap + ?Sized>(
+    a: &A,
+    b: &B,
+    direction: Vec3,
+) -> SupportPoint {
+    let support_a = a.support(direction);
+    let support_b = b.support(-direction);
+    SupportPoint {
+        point: support_a - support_b,
+        a: support_a,
+        b: support_b,
+    }
+}
+
+/// Penetration normal, depth and contact points of two overlapping convex shapes.
+pub fn penetration<A: SupportMap + ?Sized, B: SupportMap + ?Sized>(
+    a: &A,
+    b: &B,
+) -> Option<Penetration> {
+    let mut simplex = intersect(a, b)?;
+    if !blow_up(a, b, &mut simplex) {
+        return None;
+    }
+    epa(a, b, &simplex)
+}
+
+/// Whether two convex shapes overlap.
+pub fn intersects<A: SupportMap + ?Sized, B: SupportMap + ?Sized>(a: &A, b: &B) -> bool {
+    intersect(a, b).is_some()
+}
+
+/// Closest points between two separated convex shapes, `None` when they overlap.
+pub fn closest_points<A: SupportMap + ?Sized, B: SupportMap + ?Sized>(
+    a: &A,
+    b: &B,
+) -> Option<(Vec3, Vec3)> {
+    let mut direction = a.center() - b.center();
+    if direction.length_squared() < 1e-12 {
+        direction = Vec3::X;
+    }
+
+    let mut simplex = vec![support(a, b, -direction)];
+    let mut weights = vec![1.0];
+    let mut closest = simplex[0].point;
+
+    for _ in 0..MAX_ITERATIONS {
+        let distance_squared = closest.length_squared();
+        if distance_squared < DISTANCE_TOLERANCE * DISTANCE_TOLERANCE {
+            return None;
+        }
+
+        let point = support(a, b, -closest);
+        // no vertex gets meaningfully closer to the origin, the current simplex is optimal
+        if distance_squared - closest.dot(point.point) <= DISTANCE_TOLERANCE * distance_squared
+            || simplex
+                .iter()
+                .any(|vertex| vertex.point.distance_squared(point.point) < 1e-12)
+        {
+            break;
+        }
+
+        simplex.push(point);
+        (closest, weights) = closest_on_simplex(&mut simplex)?;
+    }
+
+    let point_a = simplex.iter().zip(&weights).map(|(v, w)| v.a * *w).sum();
+    let point_b = simplex.iter().zip(&weights).map(|(v, w)| v.b * *w).sum();
+    Some((point_a, point_b))
+}
+
+/// Distance between two convex shapes, zero when they overlap.
+pub fn distance<A: SupportMap + ?Sized, B: SupportMap + ?Sized>(a: &A, b: &B) -> f32 {
+    closest_points(a, b).map_or(0.0, |(point_a, point_b)| point_a.distance(point_b))
+}
+
+/// A single point, so that point queries can reuse the shape queries.
+struct Point(Vec3);
+
+impl SupportMap for Point {
+    fn support(&self, _direction: Vec3) -> Vec3 {
+        self.0
+    }
+
+    fn center(&self) -> Vec3 {
+        self.0
+    }
+}
+
+/// Distance along a ray with unit `direction` at which it first touches a convex shape, and the
+/// surface normal there. Conservative advancement: every step moves the ray point by the gap to
+/// the shape divided by how fast the ray closes that gap. Rays starting inside hit at zero.
+pub fn cast_ray<S: SupportMap + ?Sized>(
+    shape: &S,
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+) -> Option<(f32, Vec3)> {
+    let mut distance = 0.0;
+    let mut normal = -direction;
+    for _ in 0..MAX_ITERATIONS {
+        let point = origin + direction * distance;
+        let Some((closest, _)) = closest_points(shape, &Point(point)) else {
+            return Some((distance, normal));
+        };
+        let gap = point - closest;
+        let length = gap.length();
+        normal = gap / length;
+        if length < 1e-4 {
+            return Some((distance, normal));
+        }
+
+        let approach = -normal.dot(direction);
+        if approach <= 0.0 {
+            return None;
+        }
+        distance += length / approach;
+        if distance > max_distance {
+            return None;
+        }
+    }
+    None
+}
+
+/// Reduces the simplex to the smallest sub-simplex containing the point closest to the origin.
+/// Returns that point and the barycentric weights of the remaining vertices,
+/// or `None` when the origin is inside the tetrahedron.
+fn closest_on_simplex(simplex: &mut Vec<SupportPoint>) -> Option<(Vec3, Vec<f32>)> {
+    match simplex.len() {
+        1 => Some((simplex[0].point, vec![1.0])),
+        2 => Some(closest_on_segment(simplex)),
+        3 => Some(closest_on_triangle(simplex)),
+        _ => closest_on_tetrahedron(simplex),
+    }
+}
+
+fn closest_on_segment(simplex: &mut Vec<SupportPoint>) -> (Vec3, Vec<f32>) {
+    let (a, b) = (simplex[0].point, simplex[1].point);
+    let ab = b - a;
+    let t = -a.dot(ab) / ab.length_squared().max(1e-12);
+    if t <= 0.0 {
+        simplex.truncate(1);
+        (a, vec![1.0])
+    } else if t >= 1.0 {
+        simplex.remove(0);
+        (b, vec![1.0])
+    } else {
+        (a + ab * t, vec![1.0 - t, t])
+    }
+}
+
+/// Closest point to the origin on a triangle (Ericson, Real-Time Collision Detection 5.1.5).
+fn closest_on_triangle(simplex: &mut Vec<SupportPoint>) -> (Vec3, Vec<f32>) {
+    let (a, b, c) = (simplex[0].point, simplex[1].point, simplex[2].point);
+    let ab = b - a;
+    let ac = c - a;
+    let ap = -a;
+
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        *simplex = vec![simplex[0]];
+        return (a, vec![1.0]);
+    }
+
+    let bp = -b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        *simplex = vec![simplex[1]];
+        return (b, vec![1.0]);
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        let v = d1 / (d1 - d3);
+        *simplex = vec![simplex[0], simplex[1]];
+        return (a + ab * v, vec![1.0 - v, v]);
+    }
+
+    let cp = -c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        *simplex = vec![simplex[2]];
+        return (c, vec![1.0]);
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        let w = d2 / (d2 - d6);
+        *simplex = vec![simplex[0], simplex[2]];
+        return (a + ac * w, vec![1.0 - w, w]);
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+        *simplex = vec![simplex[1], simplex[2]];
+        return (b + (c - b) * w, vec![1.0 - w, w]);
+    }
+
+    let denom = 1.0 / (va + vb + vc);
+    let v = vb * denom;
+    let w = vc * denom;
+    (a + ab * v + ac * w, vec![1.0 - v - w, v, w])
+}
+
+fn closest_on_tetrahedron(simplex: &mut Vec<SupportPoint>) -> Option<(Vec3, Vec<f32>)> {
+    let faces = [[0, 1, 2, 3], [0, 2, 3, 1], [0, 3, 1, 2], [1, 3, 2, 0]];
+
+    // a flat tetrahedron cannot enclose anything, whatever the noisy sign tests below say
+    let a = simplex[0].point;
+    let (ab, ac, ad) = (
+        simplex[1].point - a,
+        simplex[2].point - a,
+        simplex[3].point - a,
+    );
+    let flat = ab.dot(ac.cross(ad)).abs() <= 1e-3 * ab.length() * ac.length() * ad.length();
+
+    let inside = !flat
+        && faces.iter().all(|&[i, j, k, opposite]| {
+            let a = simplex[i].point;
+            let normal = (simplex[j].point - a).cross(simplex[k].point - a);
+            normal.dot(-a) * normal.dot(simplex[opposite].point - a) > 0.0
+        });
+    if inside {
+        return None;
+    }
+
+    // the sign tests above are unreliable for thin tetrahedra, so every face is searched
+    let (face, point, weights) = faces
+        .iter()
+        .map(|&[i, j, k, _]| {
+            let mut face = vec![simplex[i], simplex[j], simplex[k]];
+            let (point, weights) = closest_on_triangle(&mut face);
+            (face, point, weights)
+        })
+        .min_by(|(_, x, _), (_, y, _)| x.length_squared().total_cmp(&y.length_squared()))?;
+
+    *simplex = face;
+    Some((point, weights))
+}
+
+/// GJK intersection test. On overlap the returned simplex encloses the origin,
+/// newest point first.
+fn intersect<A: SupportMap + ?Sized, B: SupportMap + ?Sized>(
+    a: &A,
+    b: &B,
+) -> Option<Vec<SupportPoint>> {
+    let mut direction = b.center() - a.center();
+    if direction.length_squared() < 1e-12 {
+        direction = Vec3::X;
+    }
+
+    let first = support(a, b, direction);
+    let mut simplex = vec![first];
+    direction = -first.point;
+
+    for _ in 0..MAX_ITERATIONS {
+        // the origin lies on the current simplex, the shapes are touching
+        if direction.length_squared() < 1e-12 {
+            return Some(simplex);
+        }
+
+        let point = support(a, b, direction);
+        if point.point.dot(direction) < 0.0 {
+            return None;
+        }
+
+        simplex.insert(0, point);
+        if next_simplex(&mut simplex, &mut direction) {
+            return Some(simplex);
+        }
+    }
+
+    None
+}
+
+fn next_simplex(simplex: &mut Vec<SupportPoint>, direction: &mut Vec3) -> bool {
+    match simplex.len() {
+        2 => line(simplex, direction),
+        3 => triangle(simplex, direction),
+        4 => tetrahedron(simplex, direction),
+        _ => false,
+    }
+}
+
+fn line(simplex: &mut Vec<SupportPoint>, direction: &mut Vec3) -> bool {
+    let (a, b) = (simplex[0].point, simplex[1].point);
+    let ab = b - a;
+    let ao = -a;
+
+    if same_direction(ab, ao) {
+        let perpendicular = ab.cross(ao).cross(ab);
+        // the origin lies on the segment itself, any direction orthogonal to it will do
+        *direction = if perpendicular.length_squared() > 1e-12 {
+            perpendicular
+        } else {
+            ab.any_orthonormal_vector()
+        };
+    } else {
+        simplex.truncate(1);
+        *direction = ao;
+    }
+
+    false
+}
+
+fn triangle(simplex: &mut Vec<SupportPoint>, direction: &mut Vec3) -> bool {
+    let (a, b, c) = (simplex[0].point, simplex[1].point, simplex[2].point);
+    let ab = b - a;
+    let ac = c - a;
+    let ao = -a;
+    let abc = ab.cross(ac);
+
+    if same_direction(abc.cross(ac), ao) {
+        if same_direction(ac, ao) {
+            *simplex = vec![simplex[0], simplex[2]];
+            *direction = ac.cross(ao).cross(ac);
+            false
+        } else {
+            simplex.truncate(2);
+            line(simplex, direction)
+        }
+    } else if same_direction(ab.cross(abc), ao) {
+        simplex.truncate(2);
+        line(simplex, direction)
+    } else if same_direction(abc, ao) {
+        *direction = abc;
+        false
+    } else {
+        simplex.swap(1, 2);
+        *direction = -abc;
+        false
+    }
+}
+
+fn tetrahedron(simplex: &mut Vec<SupportPoint>, direction: &mut Vec3) -> bool {
+    let [a, b, c, d] = [simplex[0], simplex[1], simplex[2], simplex[3]];
+    let ab = b.point - a.point;
+    let ac = c.point - a.point;
+    let ad = d.point - a.point;
+    let ao = -a.point;
+
+    let abc = ab.cross(ac);
+    let acd = ac.cross(ad);
+    let adb = ad.cross(ab);
+
+    if clearly_same_direction(abc, ao) {
+        *simplex = vec![a, b, c];
+        return triangle(simplex, direction);
+    }
+    if clearly_same_direction(acd, ao) {
+        *simplex = vec![a, c, d];
+        return triangle(simplex, direction);
+    }
+    if clearly_same_direction(adb, ao) {
+        *simplex = vec![a, d, b];
+        return triangle(simplex, direction);
+    }
+
+    true
+}
+
+/// Grows a degenerate simplex from a touching contact into a tetrahedron so EPA can start.
+fn blow_up<A: SupportMap + ?Sized, B: SupportMap + ?Sized>(
+    a: &A,
+    b: &B,
+    simplex: &mut Vec<SupportPoint>,
+) -> bool {
+    let directions = [
+        Vec3::X,
+        Vec3::NEG_X,
+        Vec3::Y,
+        Vec3::NEG_Y,
+        Vec3::Z,
+        Vec3::NEG_Z,
+    ];
+
+    if simplex.len() == 1 {
+        let origin = simplex[0].point;
+        match directions
+            .iter()
+            .map(|&direction| support(a, b, direction))
+            .find(|vertex| vertex.point.distance_squared(origin) > 1e-8)
+        {
+            Some(vertex) => simplex.push(vertex),
+            None => return false,
+        }
+    }
+
+    if simplex.len() == 2 {
+        let start = simplex[0].point;
+        let axis = (simplex[1].point - start).normalize();
+        let (u, v) = axis.any_orthonormal_pair();
+        match [u, -u, v, -v]
+            .iter()
+            .map(|&direction| support(a, b, direction))
+            .find(|vertex| (vertex.point - start).cross(axis).length_squared() > 1e-8)
+        {
+            Some(vertex) => simplex.push(vertex),
+            None => return false,
+        }
+    }
+
+    if simplex.len() == 3 {
+        let start = simplex[0].point;
+        let normal = (simplex[1].point - start).cross(simplex[2].point - start);
+        if normal.length_squared() < 1e-12 {
+            return false;
+        }
+        let above = support(a, b, normal);
+        let vertex = if (above.point - start).dot(normal).abs() > 1e-6 {
+            above
+        } else {
+            support(a, b, -normal)
+        };
+        if (vertex.point - start).dot(normal).abs() <= 1e-6 {
+            return false;
+        }
+        simplex.push(vertex);
+    }
+
+    true
+}
+
+/// Outward normal and distance to the origin of each face. Faces are oriented away from
+/// `interior` rather than the origin, which may lie on the surface for shallow contacts.
+fn face_normals(
+    polytope: &[SupportPoint],
+    faces: &[[usize; 3]],
+    interior: Vec3,
+) -> Vec<(Vec3, f32)> {
+    faces
+        .iter()
+        .map(|&[i, j, k]| {
+            let (a, b, c) = (polytope[i].point, polytope[j].point, polytope[k].point);
+            let mut normal = (b - a).cross(c - a).normalize_or_zero();
+            if normal.dot(a - interior) < 0.0 {
+                normal = -normal;
+            }
+            (normal, normal.dot(a))
+        })
+        .collect()
+}
+
+fn closest_face(normals: &[(Vec3, f32)]) -> usize {
+    normals
+        .iter()
+        .enumerate()
+        .filter(|(_, (normal, _))| *normal != Vec3::ZERO)
+        .min_by(|(_, x), (_, y)| x.1.total_cmp(&y.1))
+        .map(|(index, _)| index)
+        .unwrap_or(0)
+}
+
+/// Barycentric coordinates of `point` with respect to the triangle `a b c`.
+pub(super) fn barycentric(point: Vec3, a: Vec3, b: Vec3, c: Vec3) -> Vec3 {
+    let v0 = b - a;
+    let v1 = c - a;
+    let v2 = point - a;
+    let d00 = v0.dot(v0);
+    let d01 = v0.dot(v1);
+    let d11 = v1.dot(v1);
+    let d20 = v2.dot(v0);
+    let d21 = v2.dot(v1);
+    let denom = d00 * d11 - d01 * d01;
+    if denom.abs() < 1e-12 {
+        return Vec3::new(1.0, 0.0, 0.0);
+    }
+    let v = (d11 * d20 - d01 * d21) / denom;
+    let w = (d00 * d21 - d01 * d20) / denom;
+    Vec3::new(1.0 - v - w, v, w)
+}
+
+fn penetration_from_face(
+    polytope: &[SupportPoint],
+    [i, j, k]: [usize; 3],
+    (normal, depth): (Vec3, f32),
+) -> Penetration {
+    let (a, b, c) = (polytope[i], polytope[j], polytope[k]);
+    let weights = barycentric(normal * depth, a.point, b.point, c.point);
+    Penetration {
+        normal,
+        depth,
+        point_a: a.a * weights.x + b.a * weights.y + c.a * weights.z,
+        point_b: a.b * weights.x + b.b * weights.y + c.b * weights.z,
+    }
+}
+
+/// Expanding polytope algorithm, starting from a tetrahedron that encloses the origin.
+fn epa<A: SupportMap + ?Sized, B: SupportMap + ?Sized>(
+    a: &A,
+    b: &B,
+    simplex: &[SupportPoint],
+) -> Option<Penetration> {
+    let mut polytope = simplex.to_vec();
+    let mut faces = vec![[0, 1, 2], [0, 3, 1], [0, 2, 3], [1, 3, 2]];
+    let interior = polytope.iter().map(|vertex| vertex.point).sum::<Vec3>() / 4.0;
+    let mut normals = face_normals(&polytope, &faces, interior);
+    let mut min_face = closest_face(&normals);
+
+    for _ in 0..EPA_MAX_ITERATIONS {
+        let (normal, distance) = normals[min_face];
+        let vertex = support(a, b, normal);
+        if normal.dot(vertex.point) - distance < EPA_TOLERANCE {
+            break;
+        }
+
+        // remove every face the new vertex can see, keeping the horizon edges
+        let mut horizon: Vec<(usize, usize)> = vec![];
+        let mut index = 0;
+        while index < faces.len() {
+            let face = faces[index];
+            if same_direction(normals[index].0, vertex.point - polytope[face[0]].point) {
+                for edge in [(face[0], face[1]), (face[1], face[2]), (face[2], face[0])] {
+                    // an edge shared by two removed faces is not on the horizon
+                    if let Some(shared) = horizon.iter().position(|&(from, to)| {
+                        (from, to) == (edge.1, edge.0) || (from, to) == edge
+                    }) {
+                        horizon.swap_remove(shared);
+                    } else {
+                        horizon.push(edge);
+                    }
+                }
+                faces.swap_remove(index);
+                normals.swap_remove(index);
+            } else {
+                index += 1;
+            }
+        }
+
+        if horizon.is_empty() {
+            break;
+        }
+
+        let new_index = polytope.len();
+        polytope.push(vertex);
+        let new_faces: Vec<[usize; 3]> = horizon
+            .iter()
+            .map(|&(from, to)| [from, to, new_index])
+            .collect();
+        normals.extend(face_normals(&polytope, &new_faces, interior));
+        faces.extend(new_faces);
+        min_face = closest_face(&normals);
+    }
+
+    let face = *faces.get(min_face)?;
+    Some(penetration_from_face(&polytope, face, normals[min_face]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collisions::Collider;
+
+    fn sphere(center: Vec3, radius: f32) -> Collider {
+        let mut sphere = Collider::sphere(radius);
+        sphere.center = center;
+        sphere
+    }
+
+    fn cuboid(center: Vec3, half_size: Vec3, rotation: Quat) -> Collider {
+        Collider::from_cuboid(half_size, center, rotation)
+    }
+
+    #[test]
+    fn sphere_distance() {
+        let a = sphere(Vec3::ZERO, 1.);
+        let b = sphere(Vec3::new(1., 2., 2.), 0.5);
+
+        let (point_a, point_b) = closest_points(&a, &b).unwrap();
+        assert!((distance(&a, &b) - 1.5).abs() < 1e-3);
+        assert!(point_a.abs_diff_eq(Vec3::new(1., 2., 2.) / 3., 1e-3));
+        assert!(point_b.abs_diff_eq(Vec3::new(1., 2., 2.) * 5. / 6., 1e-3));
+    }
+
+    #[test]
+    fn box_distance_to_faces_edges_and_corners() {
+        let cuboid = cuboid(Vec3::ZERO, Vec3::ONE, Quat::IDENTITY);
+        let cases = [
+            (Vec3::new(3., 0.5, 0.), 1.5),
+            (Vec3::new(3., 3., 0.2), 8f32.sqrt() - 0.5),
+            (Vec3::new(-3., 3., 3.), 12f32.sqrt() - 0.5),
+        ];
+
+        for (center, expected) in cases {
+            let distance = distance(&cuboid, &sphere(center, 0.5));
+            assert!(
+                (distance - expected).abs() < 1e-3,
+                "{distance} != {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn rotated_boxes_distance() {
+        // a box standing on its edge, 0.5 below a flat box
+        let tilted = cuboid(
+            Vec3::ZERO,
+            Vec3::ONE,
+            Quat::from_rotation_z(std::f32::consts::FRAC_PI_4),
+        );
+        let flat = cuboid(
+            Vec3::Y * (2f32.sqrt() + 1.5),
+            Vec3::new(2., 1., 2.),
+            Quat::IDENTITY,
+        );
+
+        assert!((distance(&tilted, &flat) - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn overlapping_shapes_have_no_distance() {
+        let a = cuboid(Vec3::ZERO, Vec3::ONE, Quat::IDENTITY);
+        let b = sphere(Vec3::new(1.2, 0., 0.), 0.5);
+
+        assert!(intersects(&a, &b));
+        assert!(closest_points(&a, &b).is_none());
+        assert_eq!(distance(&a, &b), 0.);
+    }
+
+    #[test]
+    fn sphere_penetration() {
+        let a = sphere(Vec3::ZERO, 1.);
+        let b = sphere(Vec3::new(0., 1.2, 1.2) / 2f32.sqrt(), 0.5);
+
+        let contact = penetration(&a, &b).unwrap();
+        assert!(
+            (contact.depth - 0.3).abs() < 1e-2,
+            "depth {}",
+            contact.depth
+        );
+        assert!(contact
+            .normal
+            .abs_diff_eq(Vec3::new(0., 1., 1.) / 2f32.sqrt(), 1e-2));
+    }
+
+    #[test]
+    fn box_penetration() {
+        let a = cuboid(Vec3::ZERO, Vec3::ONE, Quat::IDENTITY);
+        let b = cuboid(Vec3::new(1.7, 0.2, -0.1), Vec3::ONE, Quat::IDENTITY);
+
+        let contact = penetration(&a, &b).unwrap();
+        assert!(
+            (contact.depth - 0.3).abs() < 1e-4,
+            "depth {}",
+            contact.depth
+        );
+        assert!(contact.normal.abs_diff_eq(Vec3::X, 1e-4));
+    }
+
+    #[test]
+    fn sphere_in_box_penetration() {
+        let a = cuboid(Vec3::ZERO, Vec3::new(2., 1., 2.), Quat::IDENTITY);
+        let b = sphere(Vec3::new(0.5, 1.8, -0.3), 1.);
+
+        let contact = penetration(&a, &b).unwrap();
+        assert!(
+            (contact.depth - 0.2).abs() < 1e-3,
+            "depth {}",
+            contact.depth
+        );
+        assert!(contact.normal.abs_diff_eq(Vec3::Y, 1e-3));
+        assert!(contact.point_b.abs_diff_eq(Vec3::new(0.5, 0.8, -0.3), 1e-2));
+    }
+
+    #[test]
+    fn separated_shapes_do_not_penetrate() {
+        let a = sphere(Vec3::ZERO, 1.);
+        let b = cuboid(Vec3::new(0., 0., 2.1), Vec3::ONE, Quat::IDENTITY);
+
+        assert!(!intersects(&a, &b));
+        assert!(penetration(&a, &b).is_none());
+    }
+
+    #[test]
+    fn rays_hit_spheres_at_their_surface() {
+        let sphere = sphere(Vec3::new(5., 0., 0.), 1.);
+
+        let (distance, normal) = cast_ray(&sphere, Vec3::ZERO, Vec3::X, 10.).unwrap();
+        assert!((distance - 4.).abs() < 1e-3);
+        assert!(normal.abs_diff_eq(Vec3::NEG_X, 1e-3));
+
+        assert!(cast_ray(&sphere, Vec3::ZERO, Vec3::X, 3.).is_none());
+        assert!(cast_ray(&sphere, Vec3::ZERO, Vec3::Y, 10.).is_none());
+    }
+}
@@ -0,0 +1,278 @@
+use super::trimesh::{convex_edges, TriangleSource};
+use super::{Aabb, Triangle};
+use bevy::prelude::*;
+
+/// A regular grid of heights in collider local space, centered on the origin along x and z.
+/// Every grid cell is split into two triangles.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeightField {
+    /// Row major heights, with rows running along z and columns along x.
+    heights: Vec<f32>,
+    rows: usize,
+    columns: usize,
+    /// Total size of the field along x and z, and the factor applied to the heights along y.
+    pub scale: Vec3,
+    convex_edges: Vec<[bool; 3]>,
+    aabb: Aabb,
+}
+
+impl HeightField {
+    /// Builds a field from `heights[row][column]`. Needs at least two rows of equal length, each
+    /// with at least two heights.
+    pub fn new(heights: Vec<Vec<f32>>, scale: Vec3) -> Self {
+        let rows = heights.len();
+        let columns = heights.first().map_or(0, Vec::len);
+        assert!(
+            rows >= 2 && columns >= 2,
+            "a height field needs at least 2 x 2 heights"
+        );
+        assert!(
+            heights.iter().all(|row| row.len() == columns),
+            "height field rows must all have the same length"
+        );
+
+        let mut field = Self {
+            heights: heights.into_iter().flatten().collect(),
+            rows,
+            columns,
+            scale,
+            convex_edges: vec![],
+            aabb: Aabb::new(Vec3::ZERO, Vec3::ZERO),
+        };
+
+        let vertices: Vec<Vec3> = (0..rows * columns)
+            .map(|index| field.vertex(index))
+            .collect();
+        let indices: Vec<[usize; 3]> = (0..(rows - 1) * (columns - 1) * 2)
+            .map(|index| field.triangle_indices(index))
+            .collect();
+        field.convex_edges = convex_edges(&vertices, &indices);
+        field.aabb = Aabb::from_points(vertices);
+        field
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Local position of the grid point at `row` and `column`.
+    pub fn point(&self, row: usize, column: usize) -> Vec3 {
+        let cell = self.cell_size();
+        Vec3::new(
+            column as f32 * cell.x - self.scale.x * 0.5,
+            self.heights[row * self.columns + column] * self.scale.y,
+            row as f32 * cell.y - self.scale.z * 0.5,
+        )
+    }
+
+    fn vertex(&self, index: usize) -> Vec3 {
+        self.point(index / self.columns, index % self.columns)
+    }
+
+    /// Size of a grid cell along x and z.
+    fn cell_size(&self) -> Vec2 {
+        Vec2::new(
+            self.scale.x / (self.columns - 1) as f32,
+            self.scale.z / (self.rows - 1) as f32,
+        )
+    }
+
+    /// Cell containing the local `x` and `z` coordinates, clamped to the grid.
+    fn cell_at(&self, x: f32, z: f32) -> (usize, usize) {
+        let cell = self.cell_size();
+        let row = ((z + self.scale.z * 0.5) / cell.y).floor().max(0.0) as usize;
+        let column = ((x + self.scale.x * 0.5) / cell.x).floor().max(0.0) as usize;
+        (row.min(self.rows - 2), column.min(self.columns - 2))
+    }
+
+    fn triangle_indices(&self, index: usize) -> [usize; 3] {
+        let cell = index / 2;
+        let corner = cell / (self.columns - 1) * self.columns + cell % (self.columns - 1);
+        let (next_column, next_row) = (corner + 1, corner + self.columns);
+        match index % 2 {
+            0 => [corner, next_row, next_column],
+            _ => [next_column, next_row, next_row + 1],
+        }
+    }
+
+    /// One of the two triangles of every cell, numbered row by row.
+    pub fn triangle(&self, index: usize) -> Triangle {
+        let [a, b, c] = self
+            .triangle_indices(index)
+            .map(|vertex| self.vertex(vertex));
+        Triangle::new(a, b, c)
+    }
+
+    /// Local space bounds of the whole field.
+    pub fn aabb(&self) -> Aabb {
+        self.aabb
+    }
+
+    /// Indices of the triangles whose bounds overlap `aabb`, given in local space.
+    pub fn triangles_in(&self, aabb: &Aabb) -> Vec<usize> {
+        if !self.aabb.intersects(aabb) {
+            return vec![];
+        }
+
+        let (first_row, first_column) = self.cell_at(aabb.min.x, aabb.min.z);
+        let (last_row, last_column) = self.cell_at(aabb.max.x, aabb.max.z);
+        let mut triangles = vec![];
+        for row in first_row..=last_row {
+            for column in first_column..=last_column {
+                let cell = row * (self.columns - 1) + column;
+                for index in [cell * 2, cell * 2 + 1] {
+                    if self.triangle(index).aabb().intersects(aabb) {
+                        triangles.push(index);
+                    }
+                }
+            }
+        }
+        triangles
+    }
+
+    /// Closest hit of a local space ray, walking the cells under the ray in order.
+    pub fn cast_ray(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+    ) -> Option<(f32, Vec3)> {
+        let entry = self.aabb.ray_hit(origin, direction, max_distance)?;
+        let entry_point = origin + direction * entry;
+        let (row, column) = self.cell_at(entry_point.x, entry_point.z);
+        let (mut row, mut column) = (row as isize, column as isize);
+
+        let cell = self.cell_size();
+        let step_row = if direction.z < 0.0 { -1 } else { 1 };
+        let step_column = if direction.x < 0.0 { -1 } else { 1 };
+        // distance along the ray to the next row and column boundaries, and between boundaries
+        let distance_to = |edge: f32, start: f32, dir: f32| {
+            if dir == 0.0 {
+                f32::INFINITY
+            } else {
+                (edge - start) / dir
+            }
+        };
+        let next_edge = |index: isize, step: isize| (index + (step > 0) as isize) as f32;
+        let mut next_row = distance_to(
+            next_edge(row, step_row) * cell.y - self.scale.z * 0.5,
+            origin.z,
+            direction.z,
+        );
+        let mut next_column = distance_to(
+            next_edge(column, step_column) * cell.x - self.scale.x * 0.5,
+            origin.x,
+            direction.x,
+        );
+        let row_delta = cell.y / direction.z.abs();
+        let column_delta = cell.x / direction.x.abs();
+
+        while (0..self.rows as isize - 1).contains(&row)
+            && (0..self.columns as isize - 1).contains(&column)
+        {
+            let cell = (row as usize) * (self.columns - 1) + column as usize;
+            let hit = [cell * 2, cell * 2 + 1]
+                .into_iter()
+                .filter_map(|index| {
+                    let triangle = self.triangle(index);
+                    triangle
+                        .ray_hit(origin, direction)
+                        .map(|distance| (distance, triangle.normal()))
+                })
+                .filter(|&(distance, _)| distance <= max_distance)
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            if hit.is_some() {
+                return hit;
+            }
+
+            if next_row.min(next_column) > max_distance {
+                return None;
+            }
+            if next_column < next_row {
+                column += step_column;
+                next_column += column_delta;
+            } else {
+                row += step_row;
+                next_row += row_delta;
+            }
+        }
+
+        None
+    }
+}
+
+impl TriangleSource for HeightField {
+    fn triangles_in(&self, aabb: &Aabb) -> Vec<usize> {
+        self.triangles_in(aabb)
+    }
+
+    fn triangle(&self, index: usize) -> Triangle {
+        self.triangle(index)
+    }
+
+    fn convex_edges(&self, index: usize) -> [bool; 3] {
+        self.convex_edges[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bumpy_field() -> HeightField {
+        let heights = (0..5)
+            .map(|row| {
+                (0..6)
+                    .map(|column| (row as f32 * 1.3).sin() * (column as f32 * 0.7).cos())
+                    .collect()
+            })
+            .collect();
+        HeightField::new(heights, Vec3::new(5., 0.8, 4.))
+    }
+
+    /// Closest hit over every triangle of the field.
+    fn brute_force(field: &HeightField, origin: Vec3, direction: Vec3, max: f32) -> Option<f32> {
+        (0..(field.rows() - 1) * (field.columns() - 1) * 2)
+            .filter_map(|index| field.triangle(index).ray_hit(origin, direction))
+            .filter(|&distance| distance <= max)
+            .min_by(f32::total_cmp)
+    }
+
+    #[test]
+    fn rays_walk_the_cells_to_the_closest_hit() {
+        let field = bumpy_field();
+        let mut rays = vec![];
+        for step in 0..64 {
+            let angle = step as f32 * 0.4;
+            let origin = Vec3::new(angle.cos() * 4., 1.5 - (step % 4) as f32, angle.sin() * 3.);
+            let target = Vec3::new((step % 7) as f32 - 3., -0.5, (step % 5) as f32 - 2.);
+            rays.push((origin, (target - origin).normalize()));
+        }
+        // straight down, and level along a row and a column
+        rays.push((Vec3::new(0.3, 2., -0.6), Vec3::NEG_Y));
+        rays.push((Vec3::new(-4., 0.1, 0.5), Vec3::X));
+        rays.push((Vec3::new(1.2, 0.1, 3.), Vec3::NEG_Z));
+
+        let mut hits = 0;
+        for (origin, direction) in rays {
+            for max_distance in [2., 20.] {
+                let expected = brute_force(&field, origin, direction, max_distance);
+                let hit = field.cast_ray(origin, direction, max_distance);
+                match (hit, expected) {
+                    (Some((distance, normal)), Some(expected)) => {
+                        assert!((distance - expected).abs() < 1e-4, "{origin} {direction}");
+                        assert!(normal.y > 0.0);
+                        hits += 1;
+                    }
+                    (None, None) => {}
+                    _ => panic!("{origin} {direction}: {hit:?} vs {expected:?}"),
+                }
+            }
+        }
+        assert!(hits > 20, "only {hits} rays hit the field");
+    }
+}
@@ -0,0 +1,541 @@
+use super::collider_systems::closest_points_segments;
+use super::{Collider, ColliderShape, ContactInfo};
+use bevy::prelude::*;
+
+/// Most points a manifold keeps.
+const MAX_POINTS: usize = 4;
+/// How closely the contact normal has to follow a face normal for the faces to be clipped.
+const FACE_ALIGNMENT: f32 = 0.98;
+/// Points of the previous step closer than this to a new point are taken to be the same point.
+const MATCH_DISTANCE: f32 = 0.05;
+/// How far a kept point may slide along the surface before it is dropped.
+const DRIFT_DISTANCE: f32 = 0.02;
+
+/// One point of a contact manifold.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ManifoldPoint {
+    /// Point on the surface of collider `a`.
+    pub point_a: Vec3,
+    /// Point on the surface of collider `b`.
+    pub point_b: Vec3,
+    pub depth: f32,
+    /// Points from collider `a` towards collider `b`. Points found between different child
+    /// shapes of a compound can have different normals.
+    pub normal: Vec3,
+    /// Identifies the pair of features that produced the point, so it can be matched across
+    /// frames. `None` when the shapes have no features to name.
+    pub id: Option<u32>,
+    /// Impulse the solver accumulated at this point along the normal, carried over from the
+    /// previous step to warm start the next solve.
+    pub normal_impulse: f32,
+    /// Friction impulse accumulated at this point, in world space, carried over like
+    /// `normal_impulse`.
+    pub tangent_impulse: Vec3,
+    /// `point_a` and `point_b` in the local frames of the two bodies, so that the point can be
+    /// followed as they move.
+    pub(crate) local_a: Vec3,
+    pub(crate) local_b: Vec3,
+    /// The touching child shapes the point was found between, when the colliders are compounds.
+    pub(crate) children: (Option<usize>, Option<usize>),
+}
+
+impl ManifoldPoint {
+    pub fn new(point_a: Vec3, point_b: Vec3, depth: f32, id: Option<u32>) -> Self {
+        Self {
+            point_a,
+            point_b,
+            depth,
+            normal: Vec3::ZERO,
+            id,
+            normal_impulse: 0.0,
+            tangent_impulse: Vec3::ZERO,
+            local_a: Vec3::ZERO,
+            local_b: Vec3::ZERO,
+            children: (None, None),
+        }
+    }
+
+    pub(crate) fn flip(self) -> Self {
+        Self {
+            point_a: self.point_b,
+            point_b: self.point_a,
+            normal: -self.normal,
+            local_a: self.local_b,
+            local_b: self.local_a,
+            children: (self.children.1, self.children.0),
+            ..self
+        }
+    }
+}
+
+/// Contact points of two touching colliders, given the normal from `a` to `b` and the deepest
+/// contact found by the narrow phase. Boxes get their faces clipped against each other, convex
+/// polyhedra resting on a half-space get their vertices below it, and every other pair keeps
+/// the single deepest contact.
+pub(crate) fn manifold(
+    a: &Collider,
+    b: &Collider,
+    normal: Vec3,
+    deepest: ManifoldPoint,
+) -> Vec<ManifoldPoint> {
+    use ColliderShape::*;
+
+    let points = match (&a.collider_shape, &b.collider_shape) {
+        (Cuboid, Cuboid) => cuboid_cuboid(a, b, normal),
+        (HalfSpace { .. }, Cuboid | ConvexHull(_)) => plane_vertices(a, normal, b),
+        (Cuboid | ConvexHull(_), HalfSpace { .. }) => plane_vertices(b, -normal, a)
+            .into_iter()
+            .map(ManifoldPoint::flip)
+            .collect(),
+        _ => vec![],
+    };
+
+    let mut points = if points.is_empty() {
+        vec![deepest]
+    } else {
+        reduce(points, normal)
+    };
+    for point in &mut points {
+        point.normal = normal;
+    }
+    points
+}
+
+/// Carries the manifold of one touching pair of child shapes over from the previous step,
+/// given with the same collider order. New points matching an earlier one of the same child
+/// pair, by feature id or else by position, inherit its accumulated impulse. When the narrow
+/// phase found a single point, earlier points that still touch are kept as well, so the
+/// manifold of curved or mesh contacts builds up over a few steps.
+pub(crate) fn persist(
+    current: &mut ContactInfo,
+    previous: Option<&ContactInfo>,
+    a: &Collider,
+    b: &Collider,
+) {
+    let children = (current.child_a, current.child_b);
+    if let Some(previous) = previous {
+        // the earlier points of the same child pair where the bodies are now
+        let earlier: Vec<ManifoldPoint> = previous
+            .manifold
+            .iter()
+            .filter(|point| point.children == children)
+            .map(|point| {
+                let point_a = a.center + a.rotation * point.local_a;
+                let point_b = b.center + b.rotation * point.local_b;
+                ManifoldPoint {
+                    point_a,
+                    point_b,
+                    depth: current.normal.dot(point_a - point_b),
+                    normal: current.normal,
+                    ..*point
+                }
+            })
+            .collect();
+
+        let mut matched = vec![false; earlier.len()];
+        for point in &mut current.manifold {
+            let same = earlier
+                .iter()
+                .position(|earlier| point.id.is_some() && earlier.id == point.id)
+                .or_else(|| {
+                    (0..earlier.len())
+                        .map(|index| (index, earlier[index].point_b.distance(point.point_b)))
+                        .filter(|&(_, distance)| distance < MATCH_DISTANCE)
+                        .min_by(|x, y| x.1.total_cmp(&y.1))
+                        .map(|(index, _)| index)
+                });
+            if let Some(index) = same {
+                point.normal_impulse = earlier[index].normal_impulse;
+                point.tangent_impulse = earlier[index].tangent_impulse;
+                matched[index] = true;
+            }
+        }
+
+        if current.manifold.len() == 1 {
+            for (point, matched) in earlier.into_iter().zip(matched) {
+                let drift = point.point_a - point.point_b - current.normal * point.depth;
+                if !matched && point.depth > 0.0 && drift.length() < DRIFT_DISTANCE {
+                    current.manifold.push(point);
+                }
+            }
+            current.manifold = reduce(std::mem::take(&mut current.manifold), current.normal);
+        }
+    }
+
+    for point in &mut current.manifold {
+        point.children = children;
+        point.local_a = a.rotation.inverse() * (point.point_a - a.center);
+        point.local_b = b.rotation.inverse() * (point.point_b - b.center);
+    }
+}
+
+/// Joins the contacts of every touching pair of child shapes into one contact of the two
+/// colliders. It reports the deepest of them, and its manifold holds the points of all of them.
+pub(crate) fn merge(mut infos: Vec<ContactInfo>) -> Option<ContactInfo> {
+    let deepest = (0..infos.len()).max_by(|&x, &y| {
+        infos[x]
+            .penetration_depth
+            .total_cmp(&infos[y].penetration_depth)
+    })?;
+    let manifold = infos
+        .iter()
+        .flat_map(|info| info.manifold.iter().copied())
+        .collect();
+    let mut merged = infos.swap_remove(deepest);
+    merged.manifold = manifold;
+    Some(merged)
+}
+
+/// World space axes and half extents of a cuboid collider.
+fn box_frame(cuboid: &Collider) -> ([Vec3; 3], [f32; 3]) {
+    let rotation = cuboid.rotation;
+    (
+        [rotation * Vec3::X, rotation * Vec3::Y, rotation * Vec3::Z],
+        cuboid.half_extents.to_array(),
+    )
+}
+
+/// Face of a box whose outward normal is closest to `direction`, as the axis index, its sign
+/// and how well the two line up.
+fn best_face(axes: &[Vec3; 3], direction: Vec3) -> (usize, f32, f32) {
+    (0..3)
+        .map(|axis| {
+            let alignment = axes[axis].dot(direction);
+            (axis, alignment.signum(), alignment.abs())
+        })
+        .max_by(|x, y| x.2.total_cmp(&y.2))
+        .unwrap()
+}
+
+fn cuboid_cuboid(a: &Collider, b: &Collider, normal: Vec3) -> Vec<ManifoldPoint> {
+    let (axes_a, half_a) = box_frame(a);
+    let (axes_b, half_b) = box_frame(b);
+    let face_a = best_face(&axes_a, normal);
+    let face_b = best_face(&axes_b, -normal);
+
+    if face_a.2.max(face_b.2) < FACE_ALIGNMENT {
+        return edge_edge(a, b, normal);
+    }
+
+    // the face that follows the normal best is the reference, the other box supplies the
+    // incident face that gets clipped against its sides
+    if face_a.2 >= face_b.2 {
+        clip_faces(
+            (a.center, &axes_a, &half_a, face_a),
+            (b.center, &axes_b, &half_b),
+            0,
+        )
+    } else {
+        clip_faces(
+            (b.center, &axes_b, &half_b, face_b),
+            (a.center, &axes_a, &half_a),
+            1 << 24,
+        )
+        .into_iter()
+        .map(ManifoldPoint::flip)
+        .collect()
+    }
+}
+
+/// Points of the incident box face inside the side planes of the reference face and below it.
+/// Point `a` of the result lies on the reference box.
+fn clip_faces(
+    reference: (Vec3, &[Vec3; 3], &[f32; 3], (usize, f32, f32)),
+    incident: (Vec3, &[Vec3; 3], &[f32; 3]),
+    id_base: u32,
+) -> Vec<ManifoldPoint> {
+    let (center, axes, half, (axis, sign, _)) = reference;
+    let face_normal = axes[axis] * sign;
+    let face_center = center + face_normal * half[axis];
+
+    let (incident_center, incident_axes, incident_half) = incident;
+    let (incident_axis, incident_sign, _) = best_face(incident_axes, -face_normal);
+    let incident_normal = incident_axes[incident_axis] * incident_sign;
+    let (p, q) = ((incident_axis + 1) % 3, (incident_axis + 2) % 3);
+    let (p_edge, q_edge) = (
+        incident_axes[p] * incident_half[p],
+        incident_axes[q] * incident_half[q],
+    );
+    let incident_face = incident_center + incident_normal * incident_half[incident_axis];
+    let mut polygon: Vec<(Vec3, u32)> = [(1., 1.), (-1., 1.), (-1., -1.), (1., -1.)]
+        .into_iter()
+        .enumerate()
+        .map(|(vertex, (x, y))| (incident_face + p_edge * x + q_edge * y, vertex as u32))
+        .collect();
+
+    let sides = [(axis + 1) % 3, (axis + 2) % 3];
+    for (plane, (side, direction)) in sides
+        .into_iter()
+        .flat_map(|side| [(side, 1.0), (side, -1.0)])
+        .enumerate()
+    {
+        let plane_normal = axes[side] * direction;
+        let offset = plane_normal.dot(center) + half[side];
+        polygon = clip_polygon(&polygon, plane_normal, offset, plane as u32);
+    }
+
+    let faces = (axis as u32 * 2 + (sign > 0.0) as u32) << 16
+        | (incident_axis as u32 * 2 + (incident_sign > 0.0) as u32) << 8;
+    polygon
+        .into_iter()
+        .filter_map(|(point, feature)| {
+            let depth = face_normal.dot(face_center - point);
+            (depth > 0.0).then_some(ManifoldPoint::new(
+                point + face_normal * depth,
+                point,
+                depth,
+                Some(id_base | faces | feature),
+            ))
+        })
+        .collect()
+}
+
+/// Sutherland-Hodgman step keeping the part of the polygon with `normal.dot(x) <= offset`.
+/// Vertices keep their feature, new ones are named after the clipped edge and the plane.
+fn clip_polygon(
+    polygon: &[(Vec3, u32)],
+    normal: Vec3,
+    offset: f32,
+    plane: u32,
+) -> Vec<(Vec3, u32)> {
+    let mut clipped = vec![];
+    for (edge, &(start, start_feature)) in polygon.iter().enumerate() {
+        let (end, _) = polygon[(edge + 1) % polygon.len()];
+        let (start_distance, end_distance) = (normal.dot(start) - offset, normal.dot(end) - offset);
+
+        if start_distance <= 0.0 {
+            clipped.push((start, start_feature));
+        }
+        if (start_distance <= 0.0) != (end_distance <= 0.0) {
+            let t = start_distance / (start_distance - end_distance);
+            clipped.push((start.lerp(end, t), 4 + (start_feature & 0xf) * 4 + plane));
+        }
+    }
+    clipped
+}
+
+/// Single contact between the two box edges that cross along the normal.
+fn edge_edge(a: &Collider, b: &Collider, normal: Vec3) -> Vec<ManifoldPoint> {
+    let (edge_a, code_a) = support_edge(a, normal);
+    let (edge_b, code_b) = support_edge(b, -normal);
+    let (point_a, point_b) = closest_points_segments(edge_a.0, edge_a.1, edge_b.0, edge_b.1);
+    let depth = normal.dot(point_a - point_b);
+
+    vec![ManifoldPoint::new(
+        point_a,
+        point_b,
+        depth,
+        Some(1 << 28 | code_a << 8 | code_b),
+    )]
+}
+
+/// The box edge furthest along `direction` among those most perpendicular to it, with a code
+/// naming the edge.
+fn support_edge(cuboid: &Collider, direction: Vec3) -> ((Vec3, Vec3), u32) {
+    let (axes, half) = box_frame(cuboid);
+    let along = (0..3)
+        .min_by(|&x, &y| {
+            axes[x]
+                .dot(direction)
+                .abs()
+                .total_cmp(&axes[y].dot(direction).abs())
+        })
+        .unwrap();
+
+    let mut center = cuboid.center;
+    let mut code = along as u32;
+    for axis in (0..3).filter(|&axis| axis != along) {
+        let sign = if axes[axis].dot(direction) >= 0.0 {
+            1.0
+        } else {
+            -1.0
+        };
+        center += axes[axis] * half[axis] * sign;
+        code |= ((sign > 0.0) as u32) << (2 + axis);
+    }
+
+    let offset = axes[along] * half[along];
+    ((center - offset, center + offset), code)
+}
+
+/// Vertices of a cuboid or hull below the half-space surface. Point `a` lies on the surface.
+fn plane_vertices(plane: &Collider, normal: Vec3, other: &Collider) -> Vec<ManifoldPoint> {
+    let vertices: Vec<Vec3> = match &other.collider_shape {
+        ColliderShape::Cuboid => {
+            let (axes, half) = box_frame(other);
+            (0..8)
+                .map(|corner| {
+                    (0..3).fold(other.center, |vertex, axis| {
+                        let sign = if corner & (1 << axis) == 0 { -1.0 } else { 1.0 };
+                        vertex + axes[axis] * half[axis] * sign
+                    })
+                })
+                .collect()
+        }
+        ColliderShape::ConvexHull(hull) => hull
+            .points
+            .iter()
+            .map(|&point| other.center + other.rotation * point)
+            .collect(),
+        _ => vec![],
+    };
+
+    vertices
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, vertex)| {
+            let depth = normal.dot(plane.center - vertex);
+            (depth > 0.0).then_some(ManifoldPoint::new(
+                vertex + normal * depth,
+                vertex,
+                depth,
+                Some(index as u32),
+            ))
+        })
+        .collect()
+}
+
+/// Keeps the deepest point and the three that span the largest area with it.
+fn reduce(mut points: Vec<ManifoldPoint>, normal: Vec3) -> Vec<ManifoldPoint> {
+    if points.len() <= MAX_POINTS {
+        return points;
+    }
+
+    let first = take_best(&mut points, |point| point.depth);
+    let second = take_best(&mut points, |point| {
+        point.point_b.distance_squared(first.point_b)
+    });
+    let area = |point: &ManifoldPoint, start: Vec3, end: Vec3| {
+        (point.point_b - start).cross(end - start).dot(normal)
+    };
+    let third = take_best(&mut points, |point| {
+        area(point, first.point_b, second.point_b).abs()
+    });
+    // the last point goes on the far side of the triangle's longest edges
+    let side = area(&third, first.point_b, second.point_b).signum();
+    let fourth = take_best(&mut points, |point| {
+        [
+            -side * area(point, first.point_b, second.point_b),
+            -side * area(point, second.point_b, third.point_b),
+            -side * area(point, third.point_b, first.point_b),
+        ]
+        .into_iter()
+        .fold(f32::MIN, f32::max)
+    });
+
+    vec![first, second, third, fourth]
+}
+
+/// Removes and returns the point with the highest score.
+fn take_best(
+    points: &mut Vec<ManifoldPoint>,
+    score: impl Fn(&ManifoldPoint) -> f32,
+) -> ManifoldPoint {
+    let best = (0..points.len())
+        .max_by(|&x, &y| score(&points[x]).total_cmp(&score(&points[y])))
+        .unwrap();
+    points.swap_remove(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::{FRAC_1_SQRT_2, FRAC_PI_4, TAU};
+
+    fn cuboid(half_extents: Vec3, center: Vec3, rotation: Quat) -> Collider {
+        Collider::from_cuboid(half_extents, center, rotation)
+    }
+
+    fn deepest() -> ManifoldPoint {
+        ManifoldPoint::new(Vec3::ZERO, Vec3::ZERO, 0., None)
+    }
+
+    #[test]
+    fn resting_boxes_touch_at_the_corners_of_the_smaller_face() {
+        let table = cuboid(Vec3::new(2., 0.5, 2.), Vec3::NEG_Y * 0.5, Quat::IDENTITY);
+        for rotation in [Quat::IDENTITY, Quat::from_rotation_y(0.3)] {
+            let block = cuboid(Vec3::splat(0.5), Vec3::Y * 0.4, rotation);
+            let points = manifold(&table, &block, Vec3::Y, deepest());
+            assert_eq!(points.len(), 4);
+
+            let mut ids: Vec<u32> = points.iter().map(|point| point.id.unwrap()).collect();
+            ids.sort_unstable();
+            ids.dedup();
+            assert_eq!(ids.len(), 4);
+            for point in &points {
+                assert_eq!(point.normal, Vec3::Y);
+                assert!((point.depth - 0.1).abs() < 1e-5);
+                assert!(point.point_a.y.abs() < 1e-5);
+                assert!((point.point_b.y + 0.1).abs() < 1e-5);
+                let corner = rotation.inverse() * (point.point_b - block.center);
+                assert!(corner.xz().abs().abs_diff_eq(Vec2::splat(0.5), 1e-5));
+            }
+
+            // the same contact seen from the block has every point flipped
+            let flipped = manifold(&block, &table, Vec3::NEG_Y, deepest());
+            for point in flipped {
+                assert!(points
+                    .iter()
+                    .any(|other| other.point_a.abs_diff_eq(point.point_b, 1e-5)));
+            }
+        }
+    }
+
+    #[test]
+    fn faces_hanging_over_an_edge_are_clipped_to_it() {
+        let table = cuboid(Vec3::new(2., 0.5, 2.), Vec3::NEG_Y * 0.5, Quat::IDENTITY);
+        let block = cuboid(Vec3::splat(0.5), Vec3::new(1.8, 0.4, 0.), Quat::IDENTITY);
+        let points = manifold(&table, &block, Vec3::Y, deepest());
+
+        assert_eq!(points.len(), 4);
+        let mut xs: Vec<f32> = points.iter().map(|point| point.point_b.x).collect();
+        xs.sort_by(f32::total_cmp);
+        assert!(xs[..2].iter().all(|&x| (x - 1.3).abs() < 1e-5));
+        assert!(xs[2..].iter().all(|&x| (x - 2.).abs() < 1e-5));
+    }
+
+    #[test]
+    fn crossing_faces_are_reduced_to_the_widest_four_points() {
+        // a square turned by 45 degrees on a square of the same size clips to an octagon
+        let table = cuboid(Vec3::new(0.5, 0.5, 0.5), Vec3::NEG_Y * 0.5, Quat::IDENTITY);
+        let block = cuboid(
+            Vec3::splat(0.5),
+            Vec3::Y * 0.4,
+            Quat::from_rotation_y(FRAC_PI_4),
+        );
+        let points = manifold(&table, &block, Vec3::Y, deepest());
+        assert_eq!(points.len(), MAX_POINTS);
+
+        // the widest four points of a regular octagon are every other corner, which span a
+        // square of twice its circumradius squared
+        let circumradius_squared = 0.5f32.powi(2) + (FRAC_1_SQRT_2 - 0.5).powi(2);
+        let mut corners: Vec<Vec2> = points.iter().map(|point| point.point_b.xz()).collect();
+        corners.sort_by(|a, b| a.to_angle().total_cmp(&b.to_angle()));
+        let quad: f32 = (0..4)
+            .map(|index| corners[index].perp_dot(corners[(index + 1) % 4]).abs() * 0.5)
+            .sum();
+        assert!(
+            (quad - 2. * circumradius_squared).abs() < 1e-4,
+            "area {quad}"
+        );
+    }
+
+    #[test]
+    fn reduction_keeps_the_deepest_point_and_spans_the_most_area() {
+        let points: Vec<ManifoldPoint> = (0..12)
+            .map(|index| {
+                let angle = index as f32 * TAU / 12.;
+                let point = Vec3::new(angle.cos(), 0., angle.sin());
+                ManifoldPoint::new(point, point, 0.1 + index as f32 * 0.01, Some(index))
+            })
+            .collect();
+        let reduced = reduce(points, Vec3::Y);
+
+        assert_eq!(reduced.len(), MAX_POINTS);
+        assert_eq!(reduced[0].id, Some(11));
+        let mut ids: Vec<u32> = reduced.iter().map(|point| point.id.unwrap()).collect();
+        ids.sort_unstable();
+        // a square of opposite pairs, across the circle from the deepest point
+        assert_eq!(ids, [2, 5, 8, 11]);
+    }
+}
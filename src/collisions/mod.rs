@@ -1,17 +1,78 @@
+mod aabb;
+mod broad_phase;
+mod bvh;
 mod collider_systems;
+mod contacts;
+mod convex_hull;
+mod debug;
+mod dynamic_tree;
+mod events;
+mod filter;
+mod gjk;
+mod heightfield;
+mod manifold;
+mod solver;
+mod trimesh;
 
-use crate::bodies::Velocity;
+use crate::bodies::{PhysicsSet, Velocity};
+use crate::rays::{Ray, RayHit};
+pub use aabb::Aabb;
 use bevy::prelude::*;
-use collider_systems::{detect_collisions, update_vertices};
+use bevy::render::mesh::VertexAttributeValues;
+pub use broad_phase::{BroadPhase, SweepAndPrune};
+use collider_systems::{aggregate_child_colliders, detect_collisions, update_vertices};
+pub use contacts::{Contact, Contacts};
+pub use convex_hull::ConvexHull;
+pub use debug::ColliderDebugPlugin;
+pub use dynamic_tree::DynamicTree;
+use events::{send_collision_events, send_overlap_events, SensorOverlaps, SolidContacts};
+pub use events::{
+    CollisionEnded, CollisionEventSettings, CollisionPersisted, CollisionStarted, OverlapEnded,
+    OverlapStarted,
+};
+pub use filter::{CollisionGroups, PairFilter};
+pub use gjk::{
+    cast_ray, closest_points, distance, intersects, penetration, Penetration, SupportMap,
+};
+pub use heightfield::HeightField;
+pub use manifold::ManifoldPoint;
+use solver::solve_contacts;
+pub use solver::ContactSolverSettings;
+use std::sync::Arc;
+pub use trimesh::{TriMesh, Triangle};
 
 pub struct CollisionPlugin;
 impl Plugin for CollisionPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, (update_vertices, detect_collisions));
+        app.init_resource::<BroadPhase>()
+            .init_resource::<PairFilter>()
+            .init_resource::<SensorOverlaps>()
+            .add_event::<OverlapStarted>()
+            .add_event::<OverlapEnded>()
+            .init_resource::<SolidContacts>()
+            .init_resource::<Contacts>()
+            .init_resource::<CollisionEventSettings>()
+            .init_resource::<ContactSolverSettings>()
+            .add_event::<CollisionStarted>()
+            .add_event::<CollisionEnded>()
+            .add_event::<CollisionPersisted>()
+            .add_systems(
+                Update,
+                (
+                    aggregate_child_colliders,
+                    update_vertices,
+                    detect_collisions,
+                    solve_contacts,
+                    (send_overlap_events, send_collision_events),
+                )
+                    .chain()
+                    .in_set(PhysicsSet::Collisions),
+            );
     }
 }
 
-#[derive(Debug)]
+/// Result of a narrow phase test. `normal` points from collider `a` towards collider `b`.
+#[derive(Clone, Debug)]
 pub struct ContactInfo {
     pub normal: Vec3,
     pub penetration_depth: f32,
@@ -19,9 +80,33 @@ pub struct ContactInfo {
     pub a_vel: Velocity,
     pub contact_point_b: Vec3,
     pub b_vel: Velocity,
+    /// Index of the touching child shape when collider `a` is a compound.
+    pub child_a: Option<usize>,
+    /// Index of the touching child shape when collider `b` is a compound.
+    pub child_b: Option<usize>,
+    /// Up to four contact points spanning the touching area of every touching pair of child
+    /// shapes, always including at least one.
+    pub manifold: Vec<ManifoldPoint>,
 }
 
-#[derive(Clone)]
+impl ContactInfo {
+    /// The same contact seen from the other collider.
+    pub(crate) fn flipped(&self) -> Self {
+        Self {
+            normal: -self.normal,
+            penetration_depth: self.penetration_depth,
+            contact_point_a: self.contact_point_b,
+            a_vel: self.b_vel,
+            contact_point_b: self.contact_point_a,
+            b_vel: self.a_vel,
+            child_a: self.child_b,
+            child_b: self.child_a,
+            manifold: self.manifold.iter().map(|point| point.flip()).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct ColliderVertexInfo {
     pub vertices: Vec<Vec3>,
 }
@@ -47,23 +132,67 @@ impl ColliderVertexInfo {
     }
 }
 
-#[derive(Clone)]
+/// Shape of a rigid body. Also usable as a component on the body entity or its descendants, in
+/// which case the body's collider is rebuilt as a compound of all of them every frame.
+#[derive(Component, Clone, Debug, PartialEq)]
 pub struct Collider {
     pub collider_shape: ColliderShape,
     pub center: Vec3,
     pub rotation: Quat,
     pub axes: [Vec3; 3],
     pub half_extents: Vec3,
+    pub radius: f32,
+    pub half_height: f32,
     pub vertex_info: ColliderVertexInfo,
+    /// Sensors report overlaps through events but never push bodies apart.
+    pub is_sensor: bool,
 }
 
-#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+#[derive(PartialEq, Clone, Debug, Default)]
 pub enum ColliderShape {
     #[default]
     Cuboid,
     Capsule,
     Sphere,
     Ellipsoid,
+    Cylinder,
+    Cone,
+    ConvexHull(Arc<ConvexHull>),
+    /// Triangle mesh for static bodies.
+    TriMesh(Arc<TriMesh>),
+    /// Height map terrain for static bodies.
+    HeightField(Arc<HeightField>),
+    /// Everything below the plane through the collider center with the given local `normal`.
+    HalfSpace {
+        normal: Vec3,
+    },
+    /// Child colliders whose `center` and `rotation` are relative to this collider.
+    Compound(Arc<Vec<Collider>>),
+}
+
+impl ColliderShape {
+    /// Whether only static bodies can use the shape.
+    pub fn is_static_only(&self) -> bool {
+        matches!(
+            self,
+            Self::TriMesh(_) | Self::HeightField(_) | Self::HalfSpace { .. }
+        )
+    }
+}
+
+/// Stand-in for infinity when a half-space has to act as a finite convex shape.
+pub const HALF_SPACE_EXTENT: f32 = 1e4;
+
+fn mesh_positions(mesh: &Mesh) -> Option<Vec<Vec3>> {
+    match mesh.attribute(Mesh::ATTRIBUTE_POSITION)? {
+        VertexAttributeValues::Float32x3(positions) => Some(
+            positions
+                .iter()
+                .map(|&position| Vec3::from(position))
+                .collect(),
+        ),
+        _ => None,
+    }
 }
 
 impl Collider {
@@ -100,7 +229,397 @@ impl Collider {
             rotation,
             axes,
             half_extents: half_size,
+            radius: 0.,
+            half_height: 0.,
             vertex_info,
+            is_sensor: false,
+        }
+    }
+
+    pub fn sphere(radius: f32) -> Self {
+        Self {
+            collider_shape: ColliderShape::Sphere,
+            center: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            axes: [Vec3::X, Vec3::Y, Vec3::Z],
+            half_extents: Vec3::splat(radius),
+            radius,
+            half_height: 0.,
+            vertex_info: ColliderVertexInfo { vertices: vec![] },
+            is_sensor: false,
+        }
+    }
+
+    /// A capsule aligned with the local y axis: a segment of length `2 * half_height` swept by `radius`.
+    pub fn capsule(half_height: f32, radius: f32) -> Self {
+        Self {
+            collider_shape: ColliderShape::Capsule,
+            center: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            axes: [Vec3::X, Vec3::Y, Vec3::Z],
+            half_extents: Vec3::new(radius, half_height + radius, radius),
+            radius,
+            half_height,
+            vertex_info: ColliderVertexInfo { vertices: vec![] },
+            is_sensor: false,
+        }
+    }
+
+    /// An ellipsoid with the given semi-axis lengths along its local axes.
+    pub fn ellipsoid(radii: Vec3) -> Self {
+        Self {
+            collider_shape: ColliderShape::Ellipsoid,
+            center: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            axes: [Vec3::X, Vec3::Y, Vec3::Z],
+            half_extents: radii,
+            radius: 0.,
+            half_height: 0.,
+            vertex_info: ColliderVertexInfo { vertices: vec![] },
+            is_sensor: false,
+        }
+    }
+
+    /// A cylinder aligned with the local y axis, with flat caps at `-half_height` and `half_height`.
+    pub fn cylinder(half_height: f32, radius: f32) -> Self {
+        Self {
+            collider_shape: ColliderShape::Cylinder,
+            center: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            axes: [Vec3::X, Vec3::Y, Vec3::Z],
+            half_extents: Vec3::new(radius, half_height, radius),
+            radius,
+            half_height,
+            vertex_info: ColliderVertexInfo { vertices: vec![] },
+            is_sensor: false,
+        }
+    }
+
+    /// A cone aligned with the local y axis, with its base at `-half_height` and its tip at `half_height`.
+    pub fn cone(half_height: f32, radius: f32) -> Self {
+        Self {
+            collider_shape: ColliderShape::Cone,
+            center: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            axes: [Vec3::X, Vec3::Y, Vec3::Z],
+            half_extents: Vec3::new(radius, half_height, radius),
+            radius,
+            half_height,
+            vertex_info: ColliderVertexInfo { vertices: vec![] },
+            is_sensor: false,
+        }
+    }
+
+    /// The convex hull of a point cloud given in local space. Returns `None` for coplanar points.
+    pub fn convex_hull(points: &[Vec3]) -> Option<Self> {
+        ConvexHull::new(points).map(Self::from_hull)
+    }
+
+    /// The convex hull of the vertex positions of a mesh.
+    pub fn convex_hull_from_mesh(mesh: &Mesh) -> Option<Self> {
+        ConvexHull::from_mesh(mesh).map(Self::from_hull)
+    }
+
+    fn from_hull(hull: ConvexHull) -> Self {
+        let half_extents = hull
+            .points
+            .iter()
+            .fold(Vec3::ZERO, |extents, point| extents.max(point.abs()));
+
+        Self {
+            collider_shape: ColliderShape::ConvexHull(Arc::new(hull)),
+            center: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            axes: [Vec3::X, Vec3::Y, Vec3::Z],
+            half_extents,
+            radius: 0.,
+            half_height: 0.,
+            vertex_info: ColliderVertexInfo { vertices: vec![] },
+            is_sensor: false,
         }
     }
+
+    /// A static triangle mesh from vertices and counter-clockwise wound triangles.
+    pub fn trimesh(vertices: Vec<Vec3>, indices: Vec<[usize; 3]>) -> Self {
+        Self::from_trimesh(TriMesh::new(vertices, indices))
+    }
+
+    /// A static triangle mesh from a triangle list mesh.
+    pub fn trimesh_from_mesh(mesh: &Mesh) -> Option<Self> {
+        TriMesh::from_mesh(mesh).map(Self::from_trimesh)
+    }
+
+    fn from_trimesh(trimesh: TriMesh) -> Self {
+        let aabb = trimesh.aabb();
+
+        Self {
+            collider_shape: ColliderShape::TriMesh(Arc::new(trimesh)),
+            center: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            axes: [Vec3::X, Vec3::Y, Vec3::Z],
+            half_extents: aabb.min.abs().max(aabb.max.abs()),
+            radius: 0.,
+            half_height: 0.,
+            vertex_info: ColliderVertexInfo { vertices: vec![] },
+            is_sensor: false,
+        }
+    }
+
+    /// Static terrain from `heights[row][column]`, with rows along z and columns along x. The
+    /// field spans `scale.x` by `scale.z` around its center and its heights are multiplied by `scale.y`.
+    pub fn height_field(heights: Vec<Vec<f32>>, scale: Vec3) -> Self {
+        let field = HeightField::new(heights, scale);
+        let aabb = field.aabb();
+
+        Self {
+            collider_shape: ColliderShape::HeightField(Arc::new(field)),
+            center: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            axes: [Vec3::X, Vec3::Y, Vec3::Z],
+            half_extents: aabb.min.abs().max(aabb.max.abs()),
+            radius: 0.,
+            half_height: 0.,
+            vertex_info: ColliderVertexInfo { vertices: vec![] },
+            is_sensor: false,
+        }
+    }
+
+    /// A static, infinite half-space whose surface passes through the collider center.
+    pub fn half_space(normal: Vec3) -> Self {
+        Self {
+            collider_shape: ColliderShape::HalfSpace {
+                normal: normal.normalize(),
+            },
+            center: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            axes: [Vec3::X, Vec3::Y, Vec3::Z],
+            half_extents: Vec3::splat(f32::INFINITY),
+            radius: 0.,
+            half_height: 0.,
+            vertex_info: ColliderVertexInfo { vertices: vec![] },
+            is_sensor: false,
+        }
+    }
+
+    /// A compound of child colliders, each placed at a local offset and rotation. Nested
+    /// compounds are flattened into their parent. Sensor children only report overlaps while
+    /// the other children collide, and the compound is a sensor as a whole when all of its
+    /// children are.
+    pub fn compound(children: Vec<(Vec3, Quat, Collider)>) -> Self {
+        let mut flattened = vec![];
+        for (offset, rotation, mut child) in children {
+            if let ColliderShape::Compound(grandchildren) = &child.collider_shape {
+                for grandchild in grandchildren.iter() {
+                    let mut grandchild = grandchild.clone();
+                    grandchild.center = offset + rotation * grandchild.center;
+                    grandchild.rotation = rotation * grandchild.rotation;
+                    flattened.push(grandchild);
+                }
+            } else {
+                child.center = offset;
+                child.rotation = rotation;
+                flattened.push(child);
+            }
+        }
+
+        let is_sensor = !flattened.is_empty() && flattened.iter().all(|child| child.is_sensor);
+        let mut compound = Self {
+            collider_shape: ColliderShape::Compound(Arc::new(flattened)),
+            center: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            axes: [Vec3::X, Vec3::Y, Vec3::Z],
+            half_extents: Vec3::ZERO,
+            radius: 0.,
+            half_height: 0.,
+            vertex_info: ColliderVertexInfo { vertices: vec![] },
+            is_sensor,
+        };
+        let aabb = compound.aabb();
+        compound.half_extents = aabb.min.abs().max(aabb.max.abs());
+        compound
+    }
+
+    /// The children of a compound placed in world space, or nothing for other shapes.
+    pub fn world_children(&self) -> Vec<Collider> {
+        match &self.collider_shape {
+            ColliderShape::Compound(children) => children
+                .iter()
+                .map(|child| {
+                    let mut child = child.clone();
+                    child.center = self.center + self.rotation * child.center;
+                    child.rotation = self.rotation * child.rotation;
+                    child.axes = [
+                        child.rotation * Vec3::X,
+                        child.rotation * Vec3::Y,
+                        child.rotation * Vec3::Z,
+                    ];
+                    if child.collider_shape == ColliderShape::Cuboid {
+                        child.vertex_info = ColliderVertexInfo::from_cuboid(
+                            &child.center,
+                            &child.half_extents,
+                            &child.rotation,
+                        );
+                    }
+                    child
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// World space bounds of the collider. Half-spaces are unbounded and cover everything.
+    pub fn aabb(&self) -> Aabb {
+        match &self.collider_shape {
+            ColliderShape::HalfSpace { .. } => {
+                Aabb::new(Vec3::splat(f32::MIN), Vec3::splat(f32::MAX))
+            }
+            ColliderShape::TriMesh(trimesh) => {
+                trimesh.aabb().transformed(self.center, self.rotation)
+            }
+            ColliderShape::HeightField(field) => {
+                field.aabb().transformed(self.center, self.rotation)
+            }
+            _ => self.aabb_in_frame(Vec3::ZERO, Quat::IDENTITY),
+        }
+    }
+
+    /// Bounds of the collider expressed in the frame at `origin` rotated by `rotation`.
+    pub fn aabb_in_frame(&self, origin: Vec3, rotation: Quat) -> Aabb {
+        let axes = [rotation * Vec3::X, rotation * Vec3::Y, rotation * Vec3::Z];
+        let extent = |axis: Vec3| axis.dot(self.support(axis) - origin);
+        Aabb::new(
+            Vec3::from(axes.map(|axis| -extent(-axis))),
+            Vec3::from(axes.map(extent)),
+        )
+    }
+
+    /// Casts a world space ray against the collider. Convex shapes are hit through their support
+    /// function, compounds through their children.
+    pub fn cast_ray(&self, ray: &Ray, max_distance: f32) -> Option<RayHit> {
+        let direction = ray.get_dir().normalize_or_zero();
+        let local_origin = self.rotation.inverse() * (ray.get_start() - self.center);
+        let local_direction = self.rotation.inverse() * direction;
+
+        let (distance, local_normal) = match &self.collider_shape {
+            ColliderShape::TriMesh(trimesh) => {
+                trimesh.cast_ray(local_origin, local_direction, max_distance)?
+            }
+            ColliderShape::HeightField(field) => {
+                field.cast_ray(local_origin, local_direction, max_distance)?
+            }
+            ColliderShape::HalfSpace { normal } => {
+                let approach = normal.dot(local_direction);
+                let distance = -normal.dot(local_origin) / approach;
+                if approach >= 0.0 || !(0.0..=max_distance).contains(&distance) {
+                    return None;
+                }
+                (distance, *normal)
+            }
+            ColliderShape::Compound(_) => {
+                return self
+                    .world_children()
+                    .iter()
+                    .filter_map(|child| child.cast_ray(ray, max_distance))
+                    .min_by(|a, b| a.distance.total_cmp(&b.distance));
+            }
+            _ => {
+                let (distance, normal) =
+                    gjk::cast_ray(self, ray.get_start(), direction, max_distance)?;
+                return Some(RayHit {
+                    distance,
+                    point: ray.get_start() + direction * distance,
+                    normal,
+                });
+            }
+        };
+
+        let normal = self.rotation * local_normal;
+        Some(RayHit {
+            distance,
+            point: ray.get_start() + direction * distance,
+            normal: if normal.dot(direction) > 0.0 {
+                -normal
+            } else {
+                normal
+            },
+        })
+    }
+
+    /// World space end points of the inner segment of a capsule.
+    pub fn segment(&self) -> (Vec3, Vec3) {
+        let offset = self.rotation * Vec3::Y * self.half_height;
+        (self.center - offset, self.center + offset)
+    }
+}
+
+impl SupportMap for Collider {
+    fn support(&self, direction: Vec3) -> Vec3 {
+        let local = self.rotation.inverse() * direction;
+        let local_support = match &self.collider_shape {
+            ColliderShape::Cuboid => Vec3::new(
+                self.half_extents.x.copysign(local.x),
+                self.half_extents.y.copysign(local.y),
+                self.half_extents.z.copysign(local.z),
+            ),
+            ColliderShape::Sphere => local.normalize_or_zero() * self.radius,
+            ColliderShape::Capsule => {
+                Vec3::Y * self.half_height.copysign(local.y)
+                    + local.normalize_or_zero() * self.radius
+            }
+            ColliderShape::Ellipsoid => {
+                let scaled = local * self.half_extents;
+                let length = scaled.length();
+                if length > 1e-12 {
+                    scaled * self.half_extents / length
+                } else {
+                    Vec3::ZERO
+                }
+            }
+            ColliderShape::Cylinder => {
+                Vec3::new(local.x, 0., local.z).normalize_or_zero() * self.radius
+                    + Vec3::Y * self.half_height.copysign(local.y)
+            }
+            ColliderShape::Cone => {
+                let rim = Vec3::new(local.x, 0., local.z).normalize_or_zero() * self.radius
+                    - Vec3::Y * self.half_height;
+                let tip = Vec3::Y * self.half_height;
+                if rim.dot(local) > tip.dot(local) {
+                    rim
+                } else {
+                    tip
+                }
+            }
+            ColliderShape::ConvexHull(hull) => hull.support(local),
+            ColliderShape::TriMesh(trimesh) => trimesh
+                .vertices
+                .iter()
+                .copied()
+                .max_by(|a, b| a.dot(local).total_cmp(&b.dot(local)))
+                .unwrap_or(Vec3::ZERO),
+            ColliderShape::HeightField(field) => (0..field.rows())
+                .flat_map(|row| (0..field.columns()).map(move |column| field.point(row, column)))
+                .max_by(|a, b| a.dot(local).total_cmp(&b.dot(local)))
+                .unwrap_or(Vec3::ZERO),
+            ColliderShape::HalfSpace { normal } => {
+                let tangent = local - *normal * local.dot(*normal);
+                let depth = if local.dot(*normal) < 0.0 {
+                    -HALF_SPACE_EXTENT
+                } else {
+                    0.0
+                };
+                tangent.normalize_or_zero() * HALF_SPACE_EXTENT + *normal * depth
+            }
+            ColliderShape::Compound(children) => children
+                .iter()
+                .map(|child| child.support(local))
+                .max_by(|a, b| a.dot(local).total_cmp(&b.dot(local)))
+                .unwrap_or(Vec3::ZERO),
+        };
+
+        self.center + self.rotation * local_support
+    }
+
+    fn center(&self) -> Vec3 {
+        self.center
+    }
 }
@@ -0,0 +1,575 @@
+use super::events::EntityPair;
+use super::filter::can_respond;
+use super::{Contact, Contacts};
+use crate::prelude::*;
+use bevy::prelude::*;
+
+/// Passes over every contact per step; more passes let impulses travel further through stacks.
+const ITERATIONS: usize = 8;
+/// Penetration left alone, so resting contacts keep touching instead of jittering.
+const SLOP: f32 = 0.005;
+/// Share of the remaining penetration pushed out each step.
+const CORRECTION: f32 = 0.8;
+/// How far up a contact normal has to point for the body on top to count as grounded.
+const GROUND_THRESHOLD: f32 = 0.7;
+
+#[derive(Resource)]
+pub struct ContactSolverSettings {
+    /// Contacts approaching slower than this do not bounce, so that bodies with restitution
+    /// can come to rest.
+    pub restitution_threshold: f32,
+}
+
+impl Default for ContactSolverSettings {
+    fn default() -> Self {
+        Self {
+            restitution_threshold: 2.0,
+        }
+    }
+}
+
+/// Mass properties of a body in world space, as seen by the solver.
+struct SolverBody {
+    inverse_mass: f32,
+    inverse_inertia: Mat3,
+    center_of_mass: Vec3,
+}
+
+impl SolverBody {
+    fn new(body: &RigidbodyComponent) -> Self {
+        let center_of_mass = body.collider.center + body.collider.rotation * body.center_of_mass;
+        if body.rbt == RigidbodyType::Static {
+            return Self {
+                inverse_mass: 0.0,
+                inverse_inertia: Mat3::ZERO,
+                center_of_mass,
+            };
+        }
+        Self {
+            inverse_mass: body.inverse_mass,
+            inverse_inertia: body.get_inverse_inertia_world(&body.collider.rotation),
+            center_of_mass,
+        }
+    }
+
+    /// Mass the body puts up against an impulse along `normal` at the offset `r`.
+    fn inverse_mass_along(&self, r: Vec3, normal: Vec3) -> f32 {
+        self.inverse_mass + normal.dot((self.inverse_inertia * r.cross(normal)).cross(r))
+    }
+
+    fn apply_impulse(&self, body: &mut RigidbodyComponent, r: Vec3, impulse: Vec3) {
+        if body.rbt == RigidbodyType::Static {
+            return;
+        }
+        body.velocity.linear += impulse * self.inverse_mass;
+        body.velocity.angular += self.inverse_inertia * r.cross(impulse);
+    }
+
+    fn apply_angular_impulse(&self, body: &mut RigidbodyComponent, impulse: Vec3) {
+        if body.rbt == RigidbodyType::Static {
+            return;
+        }
+        body.velocity.angular += self.inverse_inertia * impulse;
+    }
+}
+
+fn point_velocity(body: &RigidbodyComponent, r: Vec3) -> Vec3 {
+    body.velocity.linear + body.velocity.angular.cross(r)
+}
+
+struct PointConstraint {
+    /// Offsets of the contact point from the two centers of mass.
+    r_a: Vec3,
+    r_b: Vec3,
+    /// The point's own normal, since child shapes of a compound can touch along different ones.
+    normal: Vec3,
+    tangents: [Vec3; 2],
+    normal_mass: f32,
+    tangent_mass: [f32; 2],
+    /// Separating velocity the point is pushed towards.
+    bounce: f32,
+    impulse: f32,
+    tangent_impulse: Vec3,
+}
+
+struct ContactConstraint {
+    pair: EntityPair,
+    normal: Vec3,
+    tangents: [Vec3; 2],
+    static_friction: f32,
+    friction: f32,
+    rolling_friction: f32,
+    spinning_friction: f32,
+    /// Effective masses against turning about the tangents and about the normal.
+    rolling_mass: [f32; 2],
+    spinning_mass: f32,
+    rolling_impulse: Vec3,
+    spinning_impulse: f32,
+    depth: f32,
+    body_a: SolverBody,
+    body_b: SolverBody,
+    points: Vec<PointConstraint>,
+}
+
+impl ContactConstraint {
+    fn new(
+        contact: &Contact,
+        a: &RigidbodyComponent,
+        b: &RigidbodyComponent,
+        settings: &ContactSolverSettings,
+    ) -> Self {
+        let (body_a, body_b) = (SolverBody::new(a), SolverBody::new(b));
+        let normal = contact.info.normal;
+        let (t1, t2) = normal.any_orthonormal_pair();
+        let friction_combine = a.friction_combine.max(b.friction_combine);
+        let restitution = a
+            .restitution_combine
+            .max(b.restitution_combine)
+            .combine(a.restitution, b.restitution);
+        let points = contact
+            .info
+            .manifold
+            .iter()
+            .map(|point| {
+                let normal = point.normal;
+                let position = (point.point_a + point.point_b) * 0.5;
+                let r_a = position - body_a.center_of_mass;
+                let r_b = position - body_b.center_of_mass;
+                let relative = point_velocity(b, r_b) - point_velocity(a, r_a);
+                // friction along tangents of unequal effective mass would push a sliding
+                // point sideways, so the first tangent follows the slide
+                let (t1, t2) = match relative.reject_from_normalized(normal).try_normalize() {
+                    Some(slide) => (slide, normal.cross(slide)),
+                    None => normal.any_orthonormal_pair(),
+                };
+                let effective_mass = |direction: Vec3| {
+                    let inverse_mass = body_a.inverse_mass_along(r_a, direction)
+                        + body_b.inverse_mass_along(r_b, direction);
+                    if inverse_mass > 0.0 {
+                        1.0 / inverse_mass
+                    } else {
+                        0.0
+                    }
+                };
+                let approach = normal.dot(relative);
+                PointConstraint {
+                    r_a,
+                    r_b,
+                    normal,
+                    tangents: [t1, t2],
+                    normal_mass: effective_mass(normal),
+                    tangent_mass: [effective_mass(t1), effective_mass(t2)],
+                    // bounce back in proportion to how fast the point came in
+                    bounce: if approach < -settings.restitution_threshold {
+                        -restitution * approach
+                    } else {
+                        0.0
+                    },
+                    impulse: point.normal_impulse,
+                    // the normal may have turned a little since the impulse was found
+                    tangent_impulse: point.tangent_impulse.reject_from_normalized(normal),
+                }
+            })
+            .collect();
+        let angular_mass = |axis: Vec3| {
+            let inverse_mass = axis.dot((body_a.inverse_inertia + body_b.inverse_inertia) * axis);
+            if inverse_mass > 0.0 {
+                1.0 / inverse_mass
+            } else {
+                0.0
+            }
+        };
+
+        Self {
+            pair: (contact.entity_a, contact.entity_b),
+            normal,
+            tangents: [t1, t2],
+            static_friction: friction_combine.combine(a.static_friction, b.static_friction),
+            friction: friction_combine.combine(a.friction, b.friction),
+            rolling_friction: friction_combine.combine(a.rolling_friction, b.rolling_friction),
+            spinning_friction: friction_combine.combine(a.spinning_friction, b.spinning_friction),
+            rolling_mass: [angular_mass(t1), angular_mass(t2)],
+            spinning_mass: angular_mass(normal),
+            rolling_impulse: Vec3::ZERO,
+            spinning_impulse: 0.0,
+            depth: contact.info.penetration_depth,
+            body_a,
+            body_b,
+            points,
+        }
+    }
+
+    /// Applies the impulses carried over from the last step.
+    fn warm_start(&self, a: &mut RigidbodyComponent, b: &mut RigidbodyComponent) {
+        for point in &self.points {
+            let impulse = point.normal * point.impulse + point.tangent_impulse;
+            self.body_a.apply_impulse(a, point.r_a, -impulse);
+            self.body_b.apply_impulse(b, point.r_b, impulse);
+        }
+    }
+
+    /// Pushes the points apart until none of them approaches, keeping every accumulated
+    /// impulse non negative so that the bodies are never pulled together. Friction stops the
+    /// points sliding for as long as that takes no more than the static coefficient times the
+    /// normal impulse; past that the point slips and friction drops to the sliding coefficient.
+    fn solve(&mut self, a: &mut RigidbodyComponent, b: &mut RigidbodyComponent) {
+        for point in &mut self.points {
+            let relative = point_velocity(b, point.r_b) - point_velocity(a, point.r_a);
+            let mut accumulated = point.tangent_impulse;
+            for (tangent, mass) in point.tangents.iter().zip(point.tangent_mass) {
+                accumulated -= *tangent * (mass * tangent.dot(relative));
+            }
+            let magnitude = accumulated.length();
+            if magnitude > self.static_friction * point.impulse {
+                accumulated *= self.friction * point.impulse / magnitude;
+            }
+            let impulse = accumulated - point.tangent_impulse;
+            point.tangent_impulse = accumulated;
+            self.body_a.apply_impulse(a, point.r_a, -impulse);
+            self.body_b.apply_impulse(b, point.r_b, impulse);
+
+            let relative = point_velocity(b, point.r_b) - point_velocity(a, point.r_a);
+            let lambda = point.normal_mass * (point.bounce - point.normal.dot(relative));
+            let accumulated = (point.impulse + lambda).max(0.0);
+            let impulse = point.normal * (accumulated - point.impulse);
+            point.impulse = accumulated;
+            self.body_a.apply_impulse(a, point.r_a, -impulse);
+            self.body_b.apply_impulse(b, point.r_b, impulse);
+        }
+
+        if self.rolling_friction > 0.0 || self.spinning_friction > 0.0 {
+            self.solve_rolling(a, b);
+        }
+    }
+
+    /// Slows the bodies turning against each other, with torques bounded by the rolling and
+    /// spinning coefficients times the normal impulse of the whole contact.
+    fn solve_rolling(&mut self, a: &mut RigidbodyComponent, b: &mut RigidbodyComponent) {
+        let normal_impulse: f32 = self.points.iter().map(|point| point.impulse).sum();
+
+        let relative = b.velocity.angular - a.velocity.angular;
+        let mut accumulated = self.rolling_impulse;
+        for (tangent, mass) in self.tangents.iter().zip(self.rolling_mass) {
+            accumulated -= *tangent * (mass * tangent.dot(relative));
+        }
+        let limit = self.rolling_friction * normal_impulse;
+        if accumulated.length() > limit {
+            accumulated = accumulated.normalize_or_zero() * limit;
+        }
+        let rolling = accumulated - self.rolling_impulse;
+        self.rolling_impulse = accumulated;
+
+        let relative = self.normal.dot(b.velocity.angular - a.velocity.angular);
+        let limit = self.spinning_friction * normal_impulse;
+        let accumulated =
+            (self.spinning_impulse - self.spinning_mass * relative).clamp(-limit, limit);
+        let spinning = self.normal * (accumulated - self.spinning_impulse);
+        self.spinning_impulse = accumulated;
+
+        self.body_a.apply_angular_impulse(a, -(rolling + spinning));
+        self.body_b.apply_angular_impulse(b, rolling + spinning);
+    }
+
+    /// Moves the bodies out of each other along the normal, in proportion to their inverse
+    /// masses.
+    fn correct_positions(
+        &self,
+        a: &mut RigidbodyComponent,
+        tf_a: &mut Transform,
+        b: &mut RigidbodyComponent,
+        tf_b: &mut Transform,
+    ) {
+        let total_inverse_mass = self.body_a.inverse_mass + self.body_b.inverse_mass;
+        if total_inverse_mass == 0.0 {
+            return;
+        }
+        let correction =
+            self.normal * ((self.depth - SLOP).max(0.0) * CORRECTION / total_inverse_mass);
+
+        if a.rbt != RigidbodyType::Static {
+            a.grounded = -self.normal.dot(Vec3::Y) > GROUND_THRESHOLD;
+            a.collider.center -= correction * self.body_a.inverse_mass;
+            tf_a.translation = a.collider.center;
+        }
+        if b.rbt != RigidbodyType::Static {
+            b.grounded = self.normal.dot(Vec3::Y) > GROUND_THRESHOLD;
+            b.collider.center += correction * self.body_b.inverse_mass;
+            tf_b.translation = b.collider.center;
+        }
+    }
+}
+
+/// Sequential impulse solver over the contacts found this step. Impulses accumulate per
+/// manifold point across the iterations and are stored back on the points, where the next step
+/// picks them up to warm start.
+pub(crate) fn solve_contacts(
+    mut bodies: Query<(&mut RigidbodyComponent, &mut Transform)>,
+    mut contacts: ResMut<Contacts>,
+    settings: Res<ContactSolverSettings>,
+) {
+    let mut constraints: Vec<ContactConstraint> = contacts
+        .iter()
+        .filter_map(|contact| {
+            let [(a, _), (b, _)] = bodies.get_many([contact.entity_a, contact.entity_b]).ok()?;
+            // contacts kept for sleeping pairs are not solved
+            can_respond(a, b).then(|| ContactConstraint::new(contact, a, b, &settings))
+        })
+        .collect();
+    // sequential impulses depend on the order they are applied in, which the contact map does
+    // not keep from one run to the next
+    constraints.sort_unstable_by_key(|constraint| constraint.pair);
+
+    for constraint in &constraints {
+        if let Ok([(mut a, _), (mut b, _)]) =
+            bodies.get_many_mut([constraint.pair.0, constraint.pair.1])
+        {
+            constraint.warm_start(&mut a, &mut b);
+        }
+    }
+    for _ in 0..ITERATIONS {
+        for constraint in &mut constraints {
+            if let Ok([(mut a, _), (mut b, _)]) =
+                bodies.get_many_mut([constraint.pair.0, constraint.pair.1])
+            {
+                constraint.solve(&mut a, &mut b);
+            }
+        }
+    }
+
+    for constraint in &constraints {
+        if let Ok([(mut a, mut tf_a), (mut b, mut tf_b)]) =
+            bodies.get_many_mut([constraint.pair.0, constraint.pair.1])
+        {
+            constraint.correct_positions(&mut a, &mut tf_a, &mut b, &mut tf_b);
+        }
+        if let Some(contact) = contacts.get_mut(constraint.pair.0, constraint.pair.1) {
+            for (manifold_point, point) in contact.info.manifold.iter_mut().zip(&constraint.points)
+            {
+                manifold_point.normal_impulse = point.impulse;
+                manifold_point.tangent_impulse = point.tangent_impulse;
+            }
+            contact.impulse = constraint.points.iter().map(|point| point.impulse).sum();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{body, body_mut, dynamic, ground, physics_app, run, spawn, DT};
+
+    fn assert_at_rest(body: &RigidbodyComponent) {
+        assert!(
+            body.velocity.linear.length() < 1e-2,
+            "moving at {:?}",
+            body.velocity.linear
+        );
+        assert!(
+            body.velocity.angular.length() < 1e-2,
+            "turning at {:?}",
+            body.velocity.angular
+        );
+        assert!((body.collider.rotation * Vec3::Y).y > 0.999, "tipped over");
+    }
+
+    /// Normal impulses of the contact points between `a` and `b` that pass `keep`.
+    fn support(app: &App, a: Entity, b: Entity, keep: impl Fn(&ManifoldPoint) -> bool) -> f32 {
+        let contacts = app.world().resource::<Contacts>();
+        let contact = contacts.between(a, b).expect("the bodies touch");
+        contact
+            .info
+            .manifold
+            .iter()
+            .filter(|point| keep(point))
+            .map(|point| point.normal_impulse)
+            .sum()
+    }
+
+    #[test]
+    fn bodies_fall_alike_whatever_their_mass() {
+        let mut app = physics_app();
+        let [light, heavy] = [1., 10.].map(|mass| {
+            let mut ball = dynamic(Collider::sphere(0.5));
+            ball.collider.center = Vec3::new(mass, 10., 0.);
+            ball.set_mass(mass);
+            spawn(&mut app, ball)
+        });
+        run(&mut app, 30);
+
+        for ball in [light, heavy] {
+            let velocity = body(&app, ball).velocity.linear;
+            assert!((velocity.y + 9.81 * 30. * DT).abs() < 1e-3, "{velocity}");
+        }
+    }
+
+    #[test]
+    fn box_comes_to_rest_on_the_ground() {
+        let mut app = physics_app();
+        let ground = spawn(&mut app, ground());
+        let cuboid = Collider::from_cuboid(
+            Vec3::splat(0.5),
+            Vec3::Y * 1.5,
+            Quat::from_rotation_z(0.3) * Quat::from_rotation_x(0.2),
+        );
+        let crate_ = spawn(&mut app, dynamic(cuboid));
+        body_mut(&mut app, crate_).set_mass(2.);
+        run(&mut app, 240);
+
+        let resting = body(&app, crate_);
+        assert_at_rest(resting);
+        assert!(
+            (resting.collider.center.y - 0.5).abs() < 0.02,
+            "resting at {}",
+            resting.collider.center.y
+        );
+        // the ground holds up exactly the weight gained over a step
+        let support = support(&app, ground, crate_, |_| true);
+        assert!((support - 2. * 9.81 * DT).abs() < 0.01, "support {support}");
+    }
+
+    #[test]
+    fn table_settles_on_all_four_legs() {
+        let mut app = physics_app();
+        let ground = spawn(&mut app, ground());
+        let leg = Collider::from_cuboid(Vec3::new(0.05, 0.25, 0.05), Vec3::ZERO, Quat::IDENTITY);
+        let mut parts = vec![(
+            Vec3::Y * 0.55,
+            Quat::IDENTITY,
+            Collider::from_cuboid(Vec3::new(1., 0.05, 0.6), Vec3::ZERO, Quat::IDENTITY),
+        )];
+        for (x, z) in [(0.9, 0.5), (-0.9, 0.5), (-0.9, -0.5), (0.9, -0.5)] {
+            parts.push((Vec3::new(x, 0.25, z), Quat::IDENTITY, leg.clone()));
+        }
+        let mut table = Collider::compound(parts);
+        table.center = Vec3::Y * 0.05;
+        let table = spawn(&mut app, dynamic(table));
+        run(&mut app, 180);
+
+        let resting = body(&app, table);
+        assert_at_rest(resting);
+        assert!(
+            resting.collider.center.y.abs() < 0.02,
+            "resting at {}",
+            resting.collider.center.y
+        );
+        for leg in 1..5 {
+            let support = support(&app, ground, table, |point| {
+                [point.children.0, point.children.1].contains(&Some(leg))
+            });
+            // each leg carries about a quarter of the weight
+            assert!(
+                (support - 9.81 * DT / 4.).abs() < 0.01,
+                "leg {leg} carries {support}"
+            );
+        }
+    }
+
+    #[test]
+    fn block_holds_or_slides_on_a_ramp_by_its_friction() {
+        let mut app = physics_app();
+        let slope = 0.5_f32;
+        let tilt = Quat::from_rotation_z(slope);
+        spawn(
+            &mut app,
+            RigidbodyComponent::new_static(Collider::from_cuboid(
+                Vec3::new(5., 0.5, 3.),
+                Vec3::ZERO,
+                tilt,
+            )),
+        );
+        let up = tilt * Vec3::Y;
+        let downhill = Vec3::NEG_Y.reject_from_normalized(up).normalize();
+        // tan(0.5) is about 0.55
+        let [holding, sliding] = [(0.8, -1.5), (0.3, 1.5)].map(|(friction, z)| {
+            let start = up * 0.75 + Vec3::Z * z;
+            let mut block = dynamic(Collider::from_cuboid(Vec3::splat(0.25), start, tilt));
+            block.friction = friction;
+            block.static_friction = friction;
+            (spawn(&mut app, block), start)
+        });
+        let steps = 60;
+        run(&mut app, steps);
+
+        let held = body(&app, holding.0).collider.center - holding.1;
+        assert!(held.length() < 0.01, "held block moved by {held}");
+
+        let slid = body(&app, sliding.0).collider.center - sliding.1;
+        let time = steps as f32 * DT;
+        let expected = 0.5 * 9.81 * (slope.sin() - 0.3 * slope.cos()) * time * time;
+        let along = slid.dot(downhill);
+        assert!(
+            (along - expected).abs() < 0.05 * expected,
+            "slid {along} of {expected}"
+        );
+        // sliding straight down the slope, neither sideways nor off the surface
+        assert!(slid.z.abs() < 1e-3, "drifted {} sideways", slid.z);
+        assert!(
+            slid.dot(up).abs() < 0.01,
+            "left the surface by {}",
+            slid.dot(up)
+        );
+    }
+
+    #[test]
+    fn rolling_friction_brings_a_ball_to_a_stop() {
+        let mut app = physics_app();
+        spawn(&mut app, ground());
+        let [braked, free] = [(0.1, -2.), (0., 2.)].map(|(rolling_friction, z)| {
+            let mut ball = dynamic(Collider::sphere(0.5));
+            ball.collider.center = Vec3::new(-4., 0.5, z);
+            ball.rolling_friction = rolling_friction;
+            // rolling without slipping
+            ball.velocity.linear = Vec3::X * 2.;
+            ball.velocity.angular = Vec3::NEG_Z * 4.;
+            spawn(&mut app, ball)
+        });
+        run(&mut app, 150);
+
+        let stopped = body(&app, braked);
+        let velocity = stopped.velocity;
+        assert!(
+            velocity.linear.length() < 1e-2 && velocity.angular.length() < 1e-2,
+            "still moving at {velocity:?}"
+        );
+        // a torque of 0.1 times the weight slows a solid ball of radius 0.5 by g / 7, which
+        // stops it 2² / (2 g / 7) = 1.43 further on
+        let distance = stopped.collider.center.x + 4.;
+        assert!((distance - 1.43).abs() < 0.1, "stopped after {distance}");
+
+        let rolling = body(&app, free).velocity.linear;
+        assert!(
+            (rolling - Vec3::X * 2.).length() < 0.05,
+            "rolling at {rolling}"
+        );
+    }
+
+    #[test]
+    fn only_contacts_faster_than_the_threshold_bounce() {
+        let mut app = physics_app();
+        spawn(&mut app, ground());
+        // either side of the default threshold of 2
+        let [slow, fast] = [(1.5, -2.), (4., 2.)].map(|(speed, x)| {
+            let mut ball = dynamic(Collider::sphere(0.5));
+            ball.collider.center = Vec3::new(x, 0.55, 0.);
+            ball.velocity.linear = Vec3::NEG_Y * speed;
+            ball.restitution = 0.8;
+            spawn(&mut app, ball)
+        });
+        let mut rebound = [0f32; 2];
+        for _ in 0..10 {
+            run(&mut app, 1);
+            for (rebound, ball) in rebound.iter_mut().zip([slow, fast]) {
+                *rebound = rebound.max(body(&app, ball).velocity.linear.y);
+            }
+        }
+
+        assert!(rebound[0] < 0.05, "slow ball bounced at {}", rebound[0]);
+        // the fast ball touches down on the second step, having gained two steps of gravity
+        let impact = 4. + 2. * 9.81 * DT;
+        assert!(
+            (rebound[1] - 0.8 * impact).abs() < 0.05,
+            "fast ball bounced at {}",
+            rebound[1]
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use super::{bvh::Bvh, mesh_positions, Aabb, SupportMap};
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+/// A single triangle, usable as a convex shape by GJK and EPA.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Triangle {
+    pub a: Vec3,
+    pub b: Vec3,
+    pub c: Vec3,
+}
+
+impl Triangle {
+    pub fn new(a: Vec3, b: Vec3, c: Vec3) -> Self {
+        Self { a, b, c }
+    }
+
+    /// Unit normal following the counter-clockwise winding of `a b c`.
+    pub fn normal(&self) -> Vec3 {
+        (self.b - self.a).cross(self.c - self.a).normalize_or_zero()
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_points([self.a, self.b, self.c])
+    }
+
+    /// Distance along the ray `origin + direction * t` at which it crosses the triangle, from either side.
+    pub fn ray_hit(&self, origin: Vec3, direction: Vec3) -> Option<f32> {
+        let edge_ab = self.b - self.a;
+        let edge_ac = self.c - self.a;
+        let p = direction.cross(edge_ac);
+        let determinant = edge_ab.dot(p);
+        if determinant.abs() < 1e-12 {
+            return None;
+        }
+
+        let inverse = 1.0 / determinant;
+        let offset = origin - self.a;
+        let u = offset.dot(p) * inverse;
+        let q = offset.cross(edge_ab);
+        let v = direction.dot(q) * inverse;
+        let distance = edge_ac.dot(q) * inverse;
+
+        (u >= 0.0 && v >= 0.0 && u + v <= 1.0 && distance >= 0.0).then_some(distance)
+    }
+}
+
+impl SupportMap for Triangle {
+    fn support(&self, direction: Vec3) -> Vec3 {
+        [self.b, self.c].into_iter().fold(self.a, |best, vertex| {
+            if vertex.dot(direction) > best.dot(direction) {
+                vertex
+            } else {
+                best
+            }
+        })
+    }
+
+    fn center(&self) -> Vec3 {
+        (self.a + self.b + self.c) / 3.0
+    }
+}
+
+/// An indexed triangle soup in collider local space, meant for static level geometry.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TriMesh {
+    pub vertices: Vec<Vec3>,
+    pub indices: Vec<[usize; 3]>,
+    /// For each triangle edge `(i, i + 1)`, whether contacts may keep a normal pointing off the
+    /// face across it. Edges shared with a coplanar or concave neighbour never can, which keeps
+    /// shapes from catching on the seams between triangles.
+    convex_edges: Vec<[bool; 3]>,
+    bvh: Bvh,
+}
+
+impl TriMesh {
+    /// Builds a mesh from vertices and counter-clockwise wound triangles. Degenerate triangles are dropped.
+    pub fn new(vertices: Vec<Vec3>, indices: Vec<[usize; 3]>) -> Self {
+        let indices: Vec<[usize; 3]> = indices
+            .into_iter()
+            .filter(|&[a, b, c]| {
+                (vertices[b] - vertices[a])
+                    .cross(vertices[c] - vertices[a])
+                    .length_squared()
+                    > f32::EPSILON * f32::EPSILON
+            })
+            .collect();
+
+        let convex_edges = convex_edges(&vertices, &indices);
+        let triangle = |[a, b, c]: [usize; 3]| Triangle::new(vertices[a], vertices[b], vertices[c]);
+
+        let aabbs: Vec<Aabb> = indices
+            .iter()
+            .map(|&triangle_indices| triangle(triangle_indices).aabb())
+            .collect();
+        let bvh = Bvh::new(&aabbs);
+
+        Self {
+            vertices,
+            indices,
+            convex_edges,
+            bvh,
+        }
+    }
+
+    /// Builds a mesh from the positions and indices of a triangle list mesh.
+    pub fn from_mesh(mesh: &Mesh) -> Option<Self> {
+        let vertices = mesh_positions(mesh)?;
+        let flat: Vec<usize> = match mesh.indices() {
+            Some(indices) => indices.iter().collect(),
+            None => (0..vertices.len()).collect(),
+        };
+
+        let indices = flat
+            .chunks_exact(3)
+            .map(|chunk| [chunk[0], chunk[1], chunk[2]])
+            .collect();
+        Some(Self::new(vertices, indices))
+    }
+
+    pub fn triangle(&self, index: usize) -> Triangle {
+        let [a, b, c] = self.indices[index];
+        Triangle::new(self.vertices[a], self.vertices[b], self.vertices[c])
+    }
+
+    /// Local space bounds of the whole mesh.
+    pub fn aabb(&self) -> Aabb {
+        self.bvh.bounds()
+    }
+
+    /// Indices of the triangles whose bounds overlap `aabb`, given in local space.
+    pub fn triangles_in(&self, aabb: &Aabb) -> Vec<usize> {
+        let mut triangles = vec![];
+        self.bvh.query(aabb, |index| {
+            if self.triangle(index).aabb().intersects(aabb) {
+                triangles.push(index);
+            }
+        });
+        triangles
+    }
+
+    /// Closest hit of a local space ray against the mesh triangles.
+    pub fn cast_ray(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+    ) -> Option<(f32, Vec3)> {
+        let mut closest: Option<(f32, Vec3)> = None;
+        self.bvh
+            .query_ray(origin, direction, max_distance, |index| {
+                let triangle = self.triangle(index);
+                if let Some(distance) = triangle.ray_hit(origin, direction) {
+                    if distance <= closest.map_or(max_distance, |(closest, _)| closest) {
+                        closest = Some((distance, triangle.normal()));
+                    }
+                }
+            });
+        closest
+    }
+}
+
+/// Static triangle collections that convex shapes collide against one triangle at a time.
+pub(crate) trait TriangleSource {
+    /// Triangles whose bounds overlap `aabb`, given in local space.
+    fn triangles_in(&self, aabb: &Aabb) -> Vec<usize>;
+
+    fn triangle(&self, index: usize) -> Triangle;
+
+    fn convex_edges(&self, index: usize) -> [bool; 3];
+}
+
+impl TriangleSource for TriMesh {
+    fn triangles_in(&self, aabb: &Aabb) -> Vec<usize> {
+        self.triangles_in(aabb)
+    }
+
+    fn triangle(&self, index: usize) -> Triangle {
+        self.triangle(index)
+    }
+
+    fn convex_edges(&self, index: usize) -> [bool; 3] {
+        self.convex_edges[index]
+    }
+}
+
+/// For each triangle edge `(i, i + 1)`, whether the surface folds away below the triangle there.
+/// Open boundaries and non-manifold edges count as convex.
+pub(super) fn convex_edges(vertices: &[Vec3], indices: &[[usize; 3]]) -> Vec<[bool; 3]> {
+    let mut edge_triangles: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (index, &[a, b, c]) in indices.iter().enumerate() {
+        for (from, to) in [(a, b), (b, c), (c, a)] {
+            edge_triangles
+                .entry((from.min(to), from.max(to)))
+                .or_default()
+                .push(index);
+        }
+    }
+
+    indices
+        .iter()
+        .enumerate()
+        .map(|(index, &[a, b, c])| {
+            let normal = Triangle::new(vertices[a], vertices[b], vertices[c]).normal();
+            [(a, b), (b, c), (c, a)].map(|(from, to)| {
+                match edge_triangles[&(from.min(to), from.max(to))].as_slice() {
+                    [first, second] => {
+                        let neighbour = if *first == index { *second } else { *first };
+                        let opposite = indices[neighbour]
+                            .into_iter()
+                            .find(|&vertex| vertex != from && vertex != to)
+                            .unwrap();
+                        let drop = normal.dot(vertices[opposite] - vertices[from]);
+                        drop < -1e-4 * vertices[opposite].distance(vertices[from])
+                    }
+                    _ => true,
+                }
+            })
+        })
+        .collect()
+}
@@ -3,3 +3,6 @@ pub mod collisions;
 pub mod joints;
 pub mod prelude;
 pub mod rays;
+
+#[cfg(test)]
+mod testing;
@@ -18,3 +18,12 @@ impl Ray {
         self.dir
     }
 }
+
+/// Where a ray first touched a collider.
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit {
+    pub distance: f32,
+    pub point: Vec3,
+    /// Surface normal at the hit, facing back along the ray.
+    pub normal: Vec3,
+}
@@ -0,0 +1,164 @@
+use crate::prelude::*;
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use std::time::Duration;
+
+/// Length of one physics step in [`physics_app`].
+pub(crate) const DT: f32 = 1. / 60.;
+
+/// A headless app running the physics plugins in steps of [`DT`].
+pub(crate) fn physics_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        AssetPlugin::default(),
+        TransformPlugin,
+        HierarchyPlugin,
+        ZphyPlugin,
+    ))
+    .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+        DT,
+    )));
+    // the first update only starts the clock
+    app.update();
+    app
+}
+
+pub(crate) fn run(app: &mut App, steps: usize) {
+    for _ in 0..steps {
+        app.update();
+    }
+}
+
+pub(crate) fn body(app: &App, entity: Entity) -> &RigidbodyComponent {
+    app.world().get::<RigidbodyComponent>(entity).unwrap()
+}
+
+pub(crate) fn body_mut(app: &mut App, entity: Entity) -> Mut<'_, RigidbodyComponent> {
+    app.world_mut()
+        .get_mut::<RigidbodyComponent>(entity)
+        .unwrap()
+}
+
+/// Every `E` sent since [`record`] was called.
+#[derive(Resource)]
+pub(crate) struct Recorded<E>(Vec<E>);
+
+/// Keeps every `E` sent from now on, to be read back with [`recorded`].
+pub(crate) fn record<E: Event + Clone>(app: &mut App) {
+    app.insert_resource(Recorded::<E>(vec![])).add_systems(
+        Last,
+        |mut events: EventReader<E>, mut recorded: ResMut<Recorded<E>>| {
+            recorded.0.extend(events.read().cloned());
+        },
+    );
+}
+
+pub(crate) fn recorded<E: Event>(app: &App) -> &[E] {
+    &app.world().resource::<Recorded<E>>().0
+}
+
+/// Spawns `body` with a transform at the pose of its collider.
+pub(crate) fn spawn(app: &mut App, body: RigidbodyComponent) -> Entity {
+    let transform =
+        Transform::from_translation(body.collider.center).with_rotation(body.collider.rotation);
+    app.world_mut().spawn((body, transform)).id()
+}
+
+/// Undamped dynamic body of unit mass with a friction of 0.5 and no restitution.
+pub(crate) fn dynamic(collider: Collider) -> RigidbodyComponent {
+    let damping = Damping {
+        linear: 0.,
+        angular: 0.,
+    };
+    RigidbodyComponent::new_dynamic(
+        1.,
+        collider,
+        0.5,
+        Vec3::ZERO,
+        Vec3::ZERO,
+        Vec3::ZERO,
+        damping,
+        0.,
+    )
+    .unwrap()
+}
+
+/// A static floor whose top face lies at y = 0.
+pub(crate) fn ground() -> RigidbodyComponent {
+    RigidbodyComponent::new_static(Collider::from_cuboid(
+        Vec3::new(5., 0.5, 5.),
+        Vec3::NEG_Y * 0.5,
+        Quat::IDENTITY,
+    ))
+}
+
+/// Small deterministic generator, so failures can be replayed.
+pub(crate) struct Lcg(pub u64);
+
+impl Lcg {
+    /// Uniform in `[-1, 1)`.
+    pub(crate) fn next(&mut self) -> f32 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32 * 2.0 - 1.0
+    }
+
+    /// Uniform in the box from `-1` to `1` on every axis.
+    pub(crate) fn vec3(&mut self) -> Vec3 {
+        Vec3::new(self.next(), self.next(), self.next())
+    }
+}
+
+/// Boxes of assorted sizes scattered through the cube reaching `extent` from the origin.
+pub(crate) fn scattered_boxes(random: &mut Lcg, count: usize, extent: f32) -> Vec<Aabb> {
+    (0..count)
+        .map(|_| {
+            let center = random.vec3() * extent;
+            let half_size = (random.vec3() + 1.) * 0.75;
+            Aabb::new(center - half_size, center + half_size)
+        })
+        .collect()
+}
+
+/// Boxes of `count` entities at `frame`, drifting back and forth along x so their order keeps
+/// changing. Every seventh entity only shows up on odd frames.
+pub(crate) fn drifting_boxes(frame: usize, count: u32) -> Vec<(Entity, Aabb)> {
+    scattered_boxes(&mut Lcg(3), count as usize, 6.)
+        .into_iter()
+        .zip(0..count)
+        .filter(|(_, index)| index % 7 != 0 || frame % 2 == 1)
+        .map(|(aabb, index)| {
+            let drift = Vec3::X * (index as f32 * 0.37 + frame as f32 * 0.1).sin() * 3.;
+            (
+                Entity::from_raw(index),
+                Aabb::new(aabb.min + drift, aabb.max + drift),
+            )
+        })
+        .collect()
+}
+
+/// Pairs with the lower entity first, in order, so pair lists can be compared.
+pub(crate) fn normalized(pairs: Vec<(Entity, Entity)>) -> Vec<(Entity, Entity)> {
+    let mut pairs: Vec<_> = pairs
+        .into_iter()
+        .map(|(a, b)| (a.min(b), a.max(b)))
+        .collect();
+    pairs.sort_unstable();
+    pairs
+}
+
+/// Every overlapping pair of `boxes`, found by testing each against all others.
+pub(crate) fn brute_force_pairs(boxes: &[(Entity, Aabb)]) -> Vec<(Entity, Entity)> {
+    let mut pairs = vec![];
+    for (i, (a, aabb_a)) in boxes.iter().enumerate() {
+        for (b, aabb_b) in &boxes[i + 1..] {
+            if aabb_a.intersects(aabb_b) {
+                pairs.push((*a, *b));
+            }
+        }
+    }
+    normalized(pairs)
+}
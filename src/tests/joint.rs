@@ -68,7 +68,8 @@ pub(crate) fn joint_test(
                 Vec3::ZERO,
                 Damping::default(),
                 0.,
-            ),
+            )
+            .unwrap(),
             Mesh3d(meshes.add(cuboid)),
             MeshMaterial3d(mats.add(Color::WHITE)),
             Transform {
@@ -1,13 +1,13 @@
 use crate::rigidbodies::rigid_body_test;
 use bevy::prelude::*;
-use zphy::prelude::ZphyPlugin;
+use zphy::prelude::{ColliderDebugPlugin, ZphyPlugin};
 
 pub mod joint;
 pub mod rigidbodies;
 
 fn main() {
     App::new()
-        .add_plugins((DefaultPlugins, ZphyPlugin))
+        .add_plugins((DefaultPlugins, ZphyPlugin, ColliderDebugPlugin))
         .add_systems(Startup, rigid_body_test)
         .run();
 }